@@ -0,0 +1,189 @@
+use bevy::{prelude::*, render::camera::Projection::Perspective};
+use toml::{Table, Value};
+use std::fs;
+use crate::{body::{Body, Scenario}, camera::{CamFocus, CenterCam}, input::{Action, Actions, typing}};
+
+// Bookmarks live in a sidecar of the scenario file, so everyone opening it gets the same views
+const SIDECAR: &str = "bookmarks";
+const SLOTS: [Action; 9] = [
+    Action::Bookmark1, Action::Bookmark2, Action::Bookmark3,
+    Action::Bookmark4, Action::Bookmark5, Action::Bookmark6,
+    Action::Bookmark7, Action::Bookmark8, Action::Bookmark9,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookmarkFocus {
+    Body(String),
+    Point(Vec3),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub focus: BookmarkFocus,
+    pub offset: Vec3,
+    pub rotation: Quat,
+    pub fov: f32,
+}
+
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct Bookmarks([Option<Bookmark>; SLOTS.len()]);
+
+fn vec3_value(v: Vec3) -> Value {
+    Value::Table(Table::from_iter([
+        ("x".to_owned(), Value::Float(v.x.into())),
+        ("y".to_owned(), Value::Float(v.y.into())),
+        ("z".to_owned(), Value::Float(v.z.into())),
+    ]))
+}
+
+impl Bookmarks {
+    pub fn load(path: &str) -> Bookmarks {
+        let mut bookmarks = Bookmarks::default();
+        let Ok(text) = fs::read_to_string(path) else { return bookmarks };
+        let config: Value = match toml::from_str(text.as_str()) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring malformed bookmarks {path}: {e}");
+                return bookmarks;
+            },
+        };
+        let get_f = |table: &Table, key| Some(table.get(key)?.as_float()? as f32);
+        let parse_vec3 = |val: &Value| -> Option<Vec3> {
+            let table = val.as_table()?;
+            Some(Vec3::new(get_f(table, "x")?, get_f(table, "y")?, get_f(table, "z")?))
+        };
+        let parse_bookmark = |cfg: &Value| -> Option<(usize, Bookmark)> {
+            let slot = usize::try_from(cfg.get("slot")?.as_integer()?).ok()?.checked_sub(1)?;
+            let focus = match cfg.get("focus")? {
+                Value::String(name) => BookmarkFocus::Body(name.clone()),
+                point => BookmarkFocus::Point(parse_vec3(point)?),
+            };
+            let rot = cfg.get("rotation")?.as_table()?;
+            Some((slot, Bookmark {
+                focus,
+                offset: parse_vec3(cfg.get("offset")?)?,
+                rotation: Quat::from_xyzw(get_f(rot, "x")?, get_f(rot, "y")?, get_f(rot, "z")?, get_f(rot, "w")?)
+                    .normalize(),
+                fov: cfg.get("fov")?.as_float()? as f32,
+            }))
+        };
+        for cfg in config.get("bookmark").and_then(Value::as_array).map_or(&[][..], Vec::as_slice) {
+            match parse_bookmark(cfg) {
+                Some((slot, bookmark)) if slot < SLOTS.len() => bookmarks.0[slot] = Some(bookmark),
+                _ => warn!("Skipping malformed bookmark {cfg}"),
+            }
+        }
+        bookmarks
+    }
+    pub fn to_toml(&self) -> String {
+        let entries = self.0.iter().enumerate().filter_map(|(slot, bookmark)| {
+            let bookmark = bookmark.as_ref()?;
+            let r = bookmark.rotation;
+            Some(Value::Table(Table::from_iter([
+                ("slot".to_owned(), Value::Integer(slot as i64 + 1)),
+                ("focus".to_owned(), match &bookmark.focus {
+                    BookmarkFocus::Body(name) => Value::String(name.clone()),
+                    BookmarkFocus::Point(p) => vec3_value(*p),
+                }),
+                ("offset".to_owned(), vec3_value(bookmark.offset)),
+                ("rotation".to_owned(), Value::Table(Table::from_iter([
+                    ("x".to_owned(), Value::Float(r.x.into())),
+                    ("y".to_owned(), Value::Float(r.y.into())),
+                    ("z".to_owned(), Value::Float(r.z.into())),
+                    ("w".to_owned(), Value::Float(r.w.into())),
+                ]))),
+                ("fov".to_owned(), Value::Float(bookmark.fov.into())),
+            ])))
+        }).collect();
+        Table::from_iter([("bookmark".to_owned(), Value::Array(entries))]).to_string()
+    }
+}
+
+fn reload_bookmarks(mut bookmarks: ResMut<Bookmarks>, scenario: Res<Scenario>) {
+    *bookmarks = Bookmarks::load(&scenario.sidecar(SIDECAR));
+}
+
+fn bookmark_keys(
+    actions: Actions,
+    scenario: Res<Scenario>,
+    mut bookmarks: ResMut<Bookmarks>,
+    bodies: Query<(Entity, &Body)>,
+    mut cam_query: Query<(&mut Transform, &mut Projection, &mut CenterCam)>,
+) {
+    let Some(slot) = SLOTS.iter().position(|&slot| actions.just_pressed(slot)) else { return };
+    let (mut trans, mut proj, mut center) = cam_query.get_single_mut().unwrap();
+    let fov = match *proj {
+        Perspective(ref p) => p.fov,
+        _ => PerspectiveProjection::default().fov,
+    };
+    if actions.pressed(Action::SaveBookmark) {
+        let focus = match center.focus {
+            CamFocus::Entity(e) => match bodies.get(e) {
+                Ok((_, body)) => BookmarkFocus::Body(body.name.clone()),
+                Err(_) => BookmarkFocus::Point(trans.translation - center.offset),
+            },
+            CamFocus::Point(p) => BookmarkFocus::Point(p),
+        };
+        bookmarks.0[slot] = Some(Bookmark { focus, offset: center.offset, rotation: trans.rotation, fov });
+        let path = scenario.sidecar(SIDECAR);
+        match fs::write(&path, bookmarks.to_toml()) {
+            Ok(()) => info!("Saved camera bookmark {}", slot + 1),
+            Err(e) => warn!("Failed to write {path}: {e}"),
+        }
+    } else if let Some(bookmark) = &bookmarks.0[slot] {
+        center.focus = match &bookmark.focus {
+            BookmarkFocus::Body(name) => match bodies.iter().find(|(_, body)| &body.name == name) {
+                Some((e, _)) => CamFocus::Entity(e),
+                None => {
+                    warn!("Bookmark {} focuses on missing body {name}", slot + 1);
+                    CamFocus::Point(Vec3::ZERO)
+                },
+            },
+            BookmarkFocus::Point(p) => CamFocus::Point(*p),
+        };
+        center.offset = bookmark.offset;
+        if let CamFocus::Point(p) = center.focus {
+            trans.translation = p + center.offset;
+        }
+        trans.rotation = bookmark.rotation;
+        if let Perspective(p) = proj.as_mut() {
+            p.fov = bookmark.fov;
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct BookmarkPlugin;
+impl Plugin for BookmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bookmarks>()
+            .add_systems(Update, (
+                reload_bookmarks.run_if(resource_changed::<Scenario>()),
+                bookmark_keys.run_if(not(typing)),
+            ).chain());
+    }
+}
+
+#[cfg(test)]
+mod bookmark_tests {
+    use super::*;
+    #[test]
+    fn toml_round_trip() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.0[0] = Some(Bookmark {
+            focus: BookmarkFocus::Body("Alpha".to_owned()),
+            offset: Vec3::new(0., 3., 0.5),
+            rotation: Quat::from_rotation_z(0.5),
+            fov: 0.75,
+        });
+        bookmarks.0[4] = Some(Bookmark {
+            focus: BookmarkFocus::Point(Vec3::new(1., -2., 0.25)),
+            offset: Vec3::Z,
+            rotation: Quat::IDENTITY,
+            fov: 1.5,
+        });
+        let path = std::env::temp_dir().join("colonize_bookmarks.toml");
+        fs::write(&path, bookmarks.to_toml()).unwrap();
+        assert_eq!(Bookmarks::load(path.to_str().unwrap()), bookmarks);
+    }
+}
@@ -1,14 +1,15 @@
-use bevy::{prelude::*, input::mouse::*};
+use bevy::{prelude::*, ecs::system::SystemParam, input::mouse::*, transform::TransformSystem};
 use core::f32::consts::PI;
+use crate::{body::Body, frame::{FrameRoot, apply_frame}, input::{Action, Actions, action_just_pressed}, ring::RingParticle};
 
-enum CamFocus {
+pub enum CamFocus {
     Entity(Entity),
     Point(Vec3),
 }
 #[derive(Component)]
-struct CenterCam {
-    focus: CamFocus,
-    offset: Vec3,
+pub struct CenterCam {
+    pub focus: CamFocus,
+    pub offset: Vec3,
 }
 
 fn calc_fov(x: f32) -> f32 {
@@ -32,99 +33,212 @@ fn setup_cam(mut commands: Commands) {
 const ACTIVATION_MAX: f32 = 105.;
 // const ACTIVATION_S: f32 = 0.5;
 // const ACTIVATION_B: f32 = (ACTIVATION_S / ACTIVATION_MAX).exp();
-const ACTIVATION_B: f32 = 1.00477326064844708774;
-const MOVE: f32 = 1.2;
-const SPIN: f32 = 1.2;
-fn mv_cam(mut cam: Query<&mut Transform, With<Camera>>, keys: Res<Input<KeyCode>>, time: Res<Time>) {
+const ACTIVATION_B: f32 = 1.004_773_3;
+fn mv_cam(mut cam: Query<&mut Transform, With<CenterCam>>, actions: Actions, time: Res<Time>) {
     let mut cam_trans = cam.get_single_mut().unwrap();
-    let pos = keys.pressed(KeyCode::W);
-    let neg = keys.pressed(KeyCode::S);
+    let mv = actions.sensitivity().translate;
+    let spin = actions.sensitivity().spin;
+    let pos = actions.pressed(Action::Forward);
+    let neg = actions.pressed(Action::Back);
     if pos ^ neg {
         let forward = cam_trans.forward();
-        cam_trans.translation += if pos {MOVE} else {-MOVE} * time.delta_seconds() * forward;
+        cam_trans.translation += if pos {mv} else {-mv} * time.delta_seconds() * forward;
     }
-    let pos = keys.pressed(KeyCode::D);
-    let neg = keys.pressed(KeyCode::A);
+    let pos = actions.pressed(Action::Right);
+    let neg = actions.pressed(Action::Left);
     if pos ^ neg {
         let right = cam_trans.right();
-        cam_trans.translation += if pos {MOVE} else {-MOVE} * time.delta_seconds() * right;
+        cam_trans.translation += if pos {mv} else {-mv} * time.delta_seconds() * right;
     }
-    let pos = keys.pressed(KeyCode::ShiftLeft);
-    let neg = keys.pressed(KeyCode::ControlLeft);
+    let pos = actions.pressed(Action::Up);
+    let neg = actions.pressed(Action::Down);
     if pos ^ neg {
         let up = cam_trans.up();
-        cam_trans.translation += if pos {MOVE} else {-MOVE} * time.delta_seconds() * up;
+        cam_trans.translation += if pos {mv} else {-mv} * time.delta_seconds() * up;
     }
 
-    let pos = keys.pressed(KeyCode::Q);
-    let neg = keys.pressed(KeyCode::E);
+    let pos = actions.pressed(Action::RollLeft);
+    let neg = actions.pressed(Action::RollRight);
     if pos ^ neg {
         let forward = cam_trans.forward();
-        cam_trans.rotate_axis(forward, if pos {SPIN} else {-SPIN} * time.delta_seconds());
+        cam_trans.rotate_axis(forward, if pos {spin} else {-spin} * time.delta_seconds());
     }
 }
 
-const SENSITIVITY: f32 = 1e-2;
+fn orbit(trans: &mut Transform, center: &mut CenterCam, focus: Vec3, delta: Vec2, sens: f32) {
+    // let angle = Vec3::Z.angle_between(center.offset);
+    let angle = PI - trans.forward().z.acos();
+    let rot_y = Quat::from_axis_angle(trans.right(), (-sens * delta.y).clamp(0.05 * PI - angle, 0.95 * PI - angle));
+    let rot = Quat::from_rotation_z(-sens * delta.x) * rot_y;
+    center.offset = rot * center.offset;
+    trans.translation = focus + center.offset;
+    trans.rotation = -rot * trans.rotation;
+}
+
+// Panning detaches the camera from a focused entity
+fn pan(trans: &mut Transform, center: &mut CenterCam, focus: Vec3, delta: Vec2, sens: f32) {
+    let left = trans.left();
+    let forward = Vec2::new(left.y, -left.x).normalize().extend(0.);
+    let dolly = sens * (delta.x * left + delta.y * forward);
+    center.focus = CamFocus::Point(focus + dolly);
+    trans.translation += dolly;
+}
+
+// TODO: More sophisticated zooming
+fn zoom(trans: &mut Transform, proj: &mut Projection, center: &mut CenterCam, lines: f32, sens: f32) {
+    if let Perspective(p) = proj {
+        let delta = -sens * lines;
+        let len = center.offset.length();
+        let delta_vec = (delta / len) * center.offset;
+        center.offset += delta_vec;
+        trans.translation += delta_vec;
+        p.fov = calc_fov(len + delta);
+    }
+}
+
+// Positions of focus targets as rendered in the current view frame
+#[derive(SystemParam)]
+pub struct FocusPoints<'w, 's> {
+    root: Query<'w, 's, &'static Transform, (With<FrameRoot>, Without<CenterCam>)>,
+    bodies: Query<'w, 's, &'static Transform, (With<Body>, Without<CenterCam>)>,
+}
+impl FocusPoints<'_, '_> {
+    fn get(&self, focus: &CamFocus) -> Option<Vec3> {
+        match *focus {
+            CamFocus::Entity(e) => {
+                let trans = self.bodies.get(e).ok()?;
+                let root = self.root.get_single().copied().unwrap_or_default();
+                Some(root.transform_point(trans.translation))
+            },
+            CamFocus::Point(p) => Some(p),
+        }
+    }
+}
+
+// Trackpads scroll in pixels, roughly this many per wheel line
+const PIXELS_PER_LINE: f32 = 16.;
 use bevy::render::camera::Projection::Perspective;
 fn mouse_cam(
-    buttons: Res<Input<MouseButton>>,
+    actions: Actions,
+    focus_points: FocusPoints,
     mut ev_motion: EventReader<MouseMotion>,
     mut ev_scroll: EventReader<MouseWheel>,
     mut cam_query: Query<(&mut Transform, &mut Projection, &mut CenterCam), With<Camera>>
 ) {
     let (mut trans, mut proj, mut center) = cam_query.get_single_mut().unwrap();
+    let sens = *actions.sensitivity();
     let mut delta = Vec2::ZERO;
     for ev in ev_motion.read() {
         delta += ev.delta;
     }
-    if buttons.pressed(MouseButton::Right) {
-        match center.focus {
-            CamFocus::Entity(_) => (),
-            CamFocus::Point(p) => {
-                // let angle = Vec3::Z.angle_between(center.offset);
-                let angle = PI - trans.forward().z.acos();
-                let rot_y = Quat::from_axis_angle(trans.right(), (-SENSITIVITY * delta.y).clamp(0.05 * PI - angle, 0.95 * PI - angle));
-                let rot = Quat::from_rotation_z(-SENSITIVITY * delta.x) * rot_y;
-                center.offset = rot * center.offset;
-                trans.translation = p + center.offset;
-                trans.rotation = -rot * trans.rotation;
-            },
+    if let Some(focus) = focus_points.get(&center.focus) {
+        if actions.pressed(Action::Orbit) {
+            orbit(&mut trans, &mut center, focus, delta, sens.orbit);
         }
-    }
-    if buttons.pressed(MouseButton::Middle) {
-        if let CamFocus::Point(p) = &mut center.focus {
-            let left = trans.left();
-            let forward = Vec2::new(left.y, -left.x).normalize().extend(0.);
-            let dolly = 0.005 * (delta.x * left + delta.y * forward);
-            *p += dolly;
-            trans.translation += dolly;
+        if actions.pressed(Action::Pan) {
+            pan(&mut trans, &mut center, focus, delta, sens.pan);
         }
     }
     ev_motion.clear();
 
-    // TODO: More sophisticated zooming
-    if let Perspective(p) = proj.as_mut() {
-        for ev in ev_scroll.read() {
-            match ev.unit {
-                MouseScrollUnit::Line => {
-                    let delta = -0.2 * ev.y;
-                    let len = center.offset.length();
-                    let delta_vec = (delta / len) * center.offset;
-                    center.offset += delta_vec;
-                    trans.translation += delta_vec;
-                    p.fov = calc_fov(len + delta);
-                },
-                MouseScrollUnit::Pixel => (),
+    for ev in ev_scroll.read() {
+        let lines = match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / PIXELS_PER_LINE,
+        };
+        zoom(&mut trans, &mut proj, &mut center, lines, sens.zoom);
+    }
+}
+
+// Sticks stand in for mouse drags: left orbits, right pans. The analog triggers zoom.
+fn pad_cam(
+    actions: Actions,
+    focus_points: FocusPoints,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    time: Res<Time<Real>>,
+    mut cam_query: Query<(&mut Transform, &mut Projection, &mut CenterCam), With<Camera>>
+) {
+    let (mut trans, mut proj, mut center) = cam_query.get_single_mut().unwrap();
+    let sens = *actions.sensitivity();
+    let dt = time.delta_seconds();
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        let button = |button_type| button_axes.get(GamepadButton::new(gamepad, button_type)).unwrap_or(0.);
+        let left = Vec2::new(axis(GamepadAxisType::LeftStickX), -axis(GamepadAxisType::LeftStickY));
+        let right = Vec2::new(axis(GamepadAxisType::RightStickX), -axis(GamepadAxisType::RightStickY));
+        let lines = button(GamepadButtonType::RightTrigger2) - button(GamepadButtonType::LeftTrigger2);
+        if let Some(focus) = focus_points.get(&center.focus) {
+            if left != Vec2::ZERO {
+                orbit(&mut trans, &mut center, focus, sens.stick * dt * left, sens.orbit);
             }
+            if right != Vec2::ZERO {
+                pan(&mut trans, &mut center, focus, sens.stick * dt * right, sens.pan);
+            }
+        }
+        if lines != 0. {
+            zoom(&mut trans, &mut proj, &mut center, sens.trigger * dt * lines, sens.zoom);
+        }
+    }
+}
+
+fn cycle_focus(
+    focus_points: FocusPoints,
+    bodies: Query<(Entity, &Body), Without<RingParticle>>,
+    mut cam_query: Query<&mut CenterCam>,
+) {
+    let mut center = cam_query.get_single_mut().unwrap();
+    let current = match center.focus {
+        CamFocus::Entity(e) => Some(e),
+        CamFocus::Point(_) => None,
+    };
+    let mut it = bodies.iter().skip_while(|(e, _)| current.is_some_and(|c| c != *e));
+    if current.is_some() {
+        it.next();
+    }
+    center.focus = match it.next() {
+        Some((e, body)) => {
+            info!("Camera focus: {}", body.name);
+            CamFocus::Entity(e)
+        },
+        None => {
+            info!("Camera focus: free");
+            CamFocus::Point(focus_points.get(&center.focus).unwrap_or(Vec3::ZERO))
+        },
+    };
+}
+
+// Falls back to the last followed point once a focused body is despawned
+fn drop_lost_focus(bodies: Query<(), With<Body>>, mut cam_query: Query<(&Transform, &mut CenterCam)>) {
+    let (trans, mut center) = cam_query.get_single_mut().unwrap();
+    if let CamFocus::Entity(e) = center.focus {
+        if bodies.get(e).is_err() {
+            center.focus = CamFocus::Point(trans.translation - center.offset);
         }
     }
 }
 
+// Keeps the camera at its offset from a focused body as the body moves
+pub fn follow_focus(focus_points: FocusPoints, mut cam_query: Query<(&mut Transform, &CenterCam)>) {
+    let (mut trans, center) = cam_query.get_single_mut().unwrap();
+    if let (CamFocus::Entity(_), Some(focus)) = (&center.focus, focus_points.get(&center.focus)) {
+        trans.translation = focus + center.offset;
+    }
+}
+
 #[derive(Component)]
 pub struct CameraPlugin;
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_cam).
-            add_systems(Update, (mouse_cam, mv_cam));
+            add_systems(Update, (
+                drop_lost_focus,
+                mouse_cam,
+                pad_cam,
+                mv_cam,
+                cycle_focus.run_if(action_just_pressed(Action::CycleFocus)),
+            )).
+            add_systems(PostUpdate, follow_focus.after(apply_frame).before(TransformSystem::TransformPropagate));
     }
 }
@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use crate::{body::{Body, TestParticle}, input::{Action, TextEntry, action_just_pressed}};
+
+const PANEL_COLOR: Color = Color::rgba(0.08, 0.08, 0.1, 0.85);
+const IDLE_COLOR: Color = Color::rgb(0.18, 0.18, 0.22);
+const HOVER_COLOR: Color = Color::rgb(0.26, 0.26, 0.32);
+const ACTIVE_COLOR: Color = Color::rgb(0.2, 0.35, 0.6);
+const FONT_SIZE: f32 = 16.;
+
+// Body picked in the editor, shared with other tools that act on "the selected body"
+#[derive(Resource, Default)]
+pub struct Selection(pub Option<Entity>);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Field {
+    Name,
+    Mass,
+    Radius,
+    Position(usize),
+    Velocity(usize),
+    AngularVel(usize),
+}
+const FIELDS: [Field; 12] = [
+    Field::Name, Field::Mass, Field::Radius,
+    Field::Position(0), Field::Position(1), Field::Position(2),
+    Field::Velocity(0), Field::Velocity(1), Field::Velocity(2),
+    Field::AngularVel(0), Field::AngularVel(1), Field::AngularVel(2),
+];
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+impl Field {
+    fn label(self) -> String {
+        match self {
+            Field::Name => String::from("name"),
+            Field::Mass => String::from("mass"),
+            Field::Radius => String::from("radius"),
+            Field::Position(i) => format!("position {}", AXES[i]),
+            Field::Velocity(i) => format!("velocity {}", AXES[i]),
+            Field::AngularVel(i) => format!("angular vel {}", AXES[i]),
+        }
+    }
+    fn read(self, trans: &Transform, body: &Body) -> String {
+        match self {
+            Field::Name => body.name.clone(),
+            Field::Mass => format!("{:e}", body.mass),
+            Field::Radius => body.radius.to_string(),
+            Field::Position(i) => trans.translation[i].to_string(),
+            Field::Velocity(i) => body.vel[i].to_string(),
+            Field::AngularVel(i) => body.angular_vel[i].to_string(),
+        }
+    }
+    fn write(self, value: &str, trans: &mut Transform, body: &mut Body) -> Result<(), std::num::ParseFloatError> {
+        let parse = || value.trim().parse::<f32>();
+        match self {
+            Field::Name => body.name = value.to_owned(),
+            Field::Mass => body.mass = parse()?,
+            Field::Radius => body.radius = parse()?,
+            Field::Position(i) => trans.translation[i] = parse()?,
+            Field::Velocity(i) => body.vel[i] = parse()?,
+            Field::AngularVel(i) => body.angular_vel[i] = parse()?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component)]
+struct EditorPanel;
+#[derive(Component)]
+struct BodyList;
+#[derive(Component)]
+struct BodyButton(Entity);
+#[derive(Component)]
+struct FieldButton(Field);
+
+// Field being typed into and its text so far
+#[derive(Resource, Default)]
+struct Editing(Option<(Field, String)>);
+
+fn text(value: impl Into<String>) -> TextBundle {
+    TextBundle::from_section(value, TextStyle { font_size: FONT_SIZE, color: Color::WHITE, ..default() })
+}
+
+fn button() -> ButtonBundle {
+    ButtonBundle {
+        style: Style { padding: UiRect::axes(Val::Px(6.), Val::Px(2.)), ..default() },
+        background_color: IDLE_COLOR.into(),
+        ..default()
+    }
+}
+
+fn setup_editor(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                top: Val::Px(8.),
+                width: Val::Px(300.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        EditorPanel,
+    )).with_children(|panel| {
+        panel.spawn(text("Bodies"));
+        panel.spawn((
+            NodeBundle {
+                style: Style { flex_wrap: FlexWrap::Wrap, column_gap: Val::Px(4.), row_gap: Val::Px(4.), ..default() },
+                ..default()
+            },
+            BodyList,
+        ));
+        for field in FIELDS {
+            panel.spawn(NodeBundle {
+                style: Style { justify_content: JustifyContent::SpaceBetween, ..default() },
+                ..default()
+            }).with_children(|row| {
+                row.spawn(text(field.label()));
+                row.spawn((button(), FieldButton(field))).with_children(|b| {
+                    b.spawn(text(""));
+                });
+            });
+        }
+    });
+}
+
+fn toggle_editor(
+    mut panel: Query<&mut Visibility, With<EditorPanel>>,
+    mut editing: ResMut<Editing>,
+) {
+    let mut vis = panel.single_mut();
+    *vis = match *vis {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => {
+            editing.0 = None;
+            Visibility::Hidden
+        },
+    };
+}
+
+fn drop_lost_selection(mut selection: ResMut<Selection>, mut editing: ResMut<Editing>, bodies: Query<(), With<Body>>) {
+    if selection.0.is_some_and(|e| bodies.get(e).is_err()) {
+        selection.0 = None;
+        editing.0 = None;
+    }
+}
+
+// Rebuilds the list of body buttons whenever bodies come, go or get renamed
+fn update_list(
+    mut commands: Commands,
+    mut shown: Local<Vec<(Entity, String)>>,
+    list: Query<Entity, With<BodyList>>,
+    bodies: Query<(Entity, &Body), Without<TestParticle>>,
+) {
+    let current: Vec<(Entity, String)> = bodies.iter().map(|(e, body)| (e, body.name.clone())).collect();
+    if current == *shown {
+        return;
+    }
+    let list = list.single();
+    commands.entity(list).despawn_descendants().with_children(|list| {
+        for (e, name) in &current {
+            list.spawn((button(), BodyButton(*e))).with_children(|b| {
+                b.spawn(text(name.clone()));
+            });
+        }
+    });
+    *shown = current;
+}
+
+fn click_buttons(
+    mut selection: ResMut<Selection>,
+    mut editing: ResMut<Editing>,
+    bodies: Query<(&Transform, &Body)>,
+    body_buttons: Query<(&Interaction, &BodyButton), Changed<Interaction>>,
+    field_buttons: Query<(&Interaction, &FieldButton), Changed<Interaction>>,
+) {
+    for (interaction, BodyButton(e)) in &body_buttons {
+        if *interaction == Interaction::Pressed {
+            selection.0 = Some(*e);
+            editing.0 = None;
+        }
+    }
+    for (interaction, FieldButton(field)) in &field_buttons {
+        if *interaction == Interaction::Pressed {
+            if let Some((trans, body)) = selection.0.and_then(|e| bodies.get(e).ok()) {
+                editing.0 = Some((*field, field.read(trans, body)));
+            }
+        }
+    }
+}
+
+fn type_text(
+    mut editing: ResMut<Editing>,
+    mut chars: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    selection: Res<Selection>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut bodies: Query<(&mut Transform, &mut Body, &mut Handle<Mesh>)>,
+) {
+    let Some((field, buffer)) = &mut editing.0 else {
+        chars.clear();
+        return;
+    };
+    for ev in chars.read() {
+        if !ev.char.is_control() {
+            buffer.push(ev.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        buffer.pop();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        editing.0 = None;
+    } else if keys.any_just_pressed([KeyCode::Return, KeyCode::NumpadEnter]) {
+        if let Some((mut trans, mut body, mut mesh)) = selection.0.and_then(|e| bodies.get_mut(e).ok()) {
+            let radius = body.radius;
+            match field.write(buffer, &mut trans, &mut body) {
+                Ok(()) if body.radius != radius => {
+                    *mesh = meshes.add(shape::UVSphere { radius: body.radius, ..default() }.into());
+                },
+                Ok(()) => (),
+                Err(e) => warn!("Invalid {}: {e}", field.label()),
+            }
+        }
+        editing.0 = None;
+    }
+}
+
+fn button_color(interaction: Interaction, active: bool) -> BackgroundColor {
+    match interaction {
+        _ if active => ACTIVE_COLOR,
+        Interaction::Hovered | Interaction::Pressed => HOVER_COLOR,
+        Interaction::None => IDLE_COLOR,
+    }.into()
+}
+
+fn refresh_panel(
+    editing: Res<Editing>,
+    selection: Res<Selection>,
+    mut typing: ResMut<TextEntry>,
+    bodies: Query<(&Transform, &Body)>,
+    mut body_buttons: Query<(&Interaction, &mut BackgroundColor, &BodyButton), Without<FieldButton>>,
+    mut field_buttons: Query<(&Interaction, &mut BackgroundColor, &FieldButton, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    typing.0 = editing.0.is_some();
+    for (interaction, mut color, BodyButton(e)) in &mut body_buttons {
+        *color = button_color(*interaction, selection.0 == Some(*e));
+    }
+    let selected = selection.0.and_then(|e| bodies.get(e).ok());
+    for (interaction, mut color, FieldButton(field), children) in &mut field_buttons {
+        let value = match (&editing.0, selected) {
+            (Some((editing, buffer)), _) if editing == field => {
+                *color = button_color(*interaction, true);
+                format!("{buffer}_")
+            },
+            (_, Some((trans, body))) => {
+                *color = button_color(*interaction, false);
+                field.read(trans, body)
+            },
+            _ => {
+                *color = button_color(*interaction, false);
+                String::from("-")
+            },
+        };
+        if let Ok(mut text) = texts.get_mut(children[0]) {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<Editing>()
+            .add_systems(Startup, setup_editor)
+            .add_systems(Update, (
+                toggle_editor.run_if(action_just_pressed(Action::ToggleEditor)),
+                update_list,
+                (drop_lost_selection, click_buttons, type_text, refresh_panel).chain(),
+            ));
+    }
+}
@@ -0,0 +1,383 @@
+// Fast multipole gravity on the subdivision of `octree::Octree`: cubes split at their middle
+// into octants numbered as by `Octree::idx_offset`. The cells are built afresh from the
+// positions of every integrator stage rather than taken from the `Octree` that `sync_octree`
+// keeps, since that tree holds one entity per leaf, reads positions from `Transform`s that
+// intermediate stages never write, and is only rebuilt when bodies come or go. Nothing here
+// outlives a call, so there is no second tree to keep in step with it.
+use std::ops::Range;
+use bevy::{math::DVec3, prelude::*};
+use crate::{body::{G, par_map}, octree::Octree};
+
+// Leaves hold up to this many points, which are summed directly between neighbours; coincident points stop splitting at the depth limit
+const LEAF_SIZE: usize = 64;
+const MAX_DEPTH: u32 = 24;
+
+// Multi-indices α = (a, b, c) up to an order, graded by |α|, for Cartesian Taylor expansions
+struct Indices {
+    order: usize,
+    alphas: Vec<[usize; 3]>,
+    lookup: Vec<usize>,
+    // 1 / α!
+    inv_factorial: Vec<f64>,
+    // Terms of the derivative recurrence for each α past the first
+    recurrence: Vec<Vec<RecurrenceTerm>>,
+}
+// (axis i, α - e_i, (2|α| - 1) α_i / |α|, α - 2e_i, (|α| - 1) α_i (α_i - 1) / |α|)
+type RecurrenceTerm = (usize, usize, f64, usize, f64);
+impl Indices {
+    fn new(order: usize) -> Self {
+        let mut alphas = Vec::new();
+        for n in 0..=order {
+            for a in (0..=n).rev() {
+                for b in (0..=n - a).rev() {
+                    alphas.push([a, b, n - a - b]);
+                }
+            }
+        }
+        let side = order + 1;
+        let mut lookup = vec![usize::MAX; side * side * side];
+        for (k, &[a, b, c]) in alphas.iter().enumerate() {
+            lookup[(a * side + b) * side + c] = k;
+        }
+        let factorial = |n: usize| (1..=n).map(|k| k as f64).product::<f64>();
+        let inv_factorial = alphas.iter().map(|&[a, b, c]| 1. / (factorial(a) * factorial(b) * factorial(c))).collect();
+        let mut indices = Indices { order, alphas, lookup, inv_factorial, recurrence: Vec::new() };
+        indices.recurrence = indices.alphas.iter().map(|&alpha| {
+            let n = alpha.iter().sum::<usize>() as f64;
+            (0..3).filter(|&axis| alpha[axis] > 0).map(|axis| {
+                let m = alpha[axis] as f64;
+                let mut lower = alpha;
+                lower[axis] -= 1;
+                let once = indices.get(lower);
+                if lower[axis] == 0 {
+                    return (axis, once, (2. * n - 1.) * m / n, 0, 0.);
+                }
+                lower[axis] -= 1;
+                (axis, once, (2. * n - 1.) * m / n, indices.get(lower), (n - 1.) * m * (m - 1.) / n)
+            }).collect()
+        }).collect();
+        indices
+    }
+    fn len(&self) -> usize {
+        self.alphas.len()
+    }
+    fn get(&self, [a, b, c]: [usize; 3]) -> usize {
+        let side = self.order + 1;
+        self.lookup[(a * side + b) * side + c]
+    }
+    // d^α / α! for every α
+    fn monomials(&self, d: DVec3) -> Vec<f64> {
+        let powers = |x: f64| {
+            let mut p = vec![1.; self.order + 1];
+            for k in 1..=self.order {
+                p[k] = p[k - 1] * x;
+            }
+            p
+        };
+        let (px, py, pz) = (powers(d.x), powers(d.y), powers(d.z));
+        self.alphas.iter().zip(&self.inv_factorial).map(|(&[a, b, c], f)| px[a] * py[b] * pz[c] * f).collect()
+    }
+    // Derivatives ∂^α of 1/|r| for every α, written into `d`, from the recurrence
+    // |α| r² D_α + (2|α| - 1) Σ α_i r_i D_{α-e_i} + (|α| - 1) Σ α_i (α_i - 1) D_{α-2e_i} = 0
+    fn derivatives(&self, r: DVec3, d: &mut [f64]) {
+        let r2 = r.length_squared();
+        d[0] = 1. / r2.sqrt();
+        for k in 1..self.len() {
+            let sum: f64 = self.recurrence[k].iter()
+                .map(|&(axis, once, c1, twice, c2)| c1 * r[axis] * d[once] + c2 * d[twice])
+                .sum();
+            d[k] = -sum / r2;
+        }
+    }
+}
+
+// Expansion bookkeeping for one order, shared by every cell
+struct Expansions {
+    indices: Indices,
+    // (α, β, α - β) for β ≤ α, to shift multipoles up and locals down the tree
+    shifts: Vec<(usize, usize, usize)>,
+    // (β, α, α + β, (-1)^|α|) for |α| + |β| ≤ order, turning multipoles into locals
+    m2l: Vec<(usize, usize, usize, f64)>,
+    // (β, [β + e_x, β + e_y, β + e_z]) for |β| < order, the gradient of a local expansion
+    gradient: Vec<(usize, [usize; 3])>,
+}
+impl Expansions {
+    fn new(order: usize) -> Self {
+        let indices = Indices::new(order);
+        let degree = |alpha: &[usize; 3]| alpha.iter().sum::<usize>();
+        let mut shifts = Vec::new();
+        let mut m2l = Vec::new();
+        let mut gradient = Vec::new();
+        for (i, a) in indices.alphas.iter().enumerate() {
+            for (j, b) in indices.alphas.iter().enumerate() {
+                if (0..3).all(|k| b[k] <= a[k]) {
+                    shifts.push((i, j, indices.get([a[0] - b[0], a[1] - b[1], a[2] - b[2]])));
+                }
+                if degree(a) + degree(b) <= order {
+                    let sign = if degree(a) % 2 == 0 { 1. } else { -1. };
+                    m2l.push((j, i, indices.get([a[0] + b[0], a[1] + b[1], a[2] + b[2]]), sign));
+                }
+            }
+            if degree(a) < order {
+                let [x, y, z] = *a;
+                gradient.push((i, [indices.get([x + 1, y, z]), indices.get([x, y + 1, z]), indices.get([x, y, z + 1])]));
+            }
+        }
+        Expansions { indices, shifts, m2l, gradient }
+    }
+}
+
+// Cube of the subdivision with the points it holds, in the layout of `Octree`
+struct Cell {
+    center: DVec3,
+    // Distance from the center to the farthest point held
+    radius: f64,
+    mass: f64,
+    points: Range<usize>,
+    children: Vec<usize>,
+}
+
+struct Tree<'a> {
+    positions: &'a [Vec3],
+    masses: &'a [f32],
+    // Point indices, grouped so that every cell holds a contiguous range
+    order: Vec<usize>,
+    cells: Vec<Cell>,
+}
+impl<'a> Tree<'a> {
+    fn new(positions: &'a [Vec3], masses: &'a [f32]) -> Self {
+        let mut tree = Tree { positions, masses, order: (0..positions.len()).collect(), cells: Vec::new() };
+        let min = positions.iter().fold(Vec3::INFINITY, |min, p| min.min(*p));
+        let max = positions.iter().fold(Vec3::NEG_INFINITY, |max, p| max.max(*p));
+        // Slightly oversized so the far faces still count as inside
+        let size = (max - min).max_element().max(f32::EPSILON) * (1. + 1e-4);
+        tree.subdivide(min, size, 0..positions.len(), 0);
+        tree
+    }
+    // Splits a cube into octants the way `Octree` does, returning the new cell
+    fn subdivide(&mut self, pos: Vec3, size: f32, points: Range<usize>, depth: u32) -> usize {
+        let center = (pos + Vec3::splat(0.5 * size)).as_dvec3();
+        let radius = self.order[points.clone()].iter()
+            .map(|&i| self.positions[i].as_dvec3().distance(center))
+            .fold(0., f64::max);
+        let mass = self.order[points.clone()].iter().map(|&i| self.masses[i] as f64).sum();
+        let index = self.cells.len();
+        self.cells.push(Cell { center, radius, mass, points: points.clone(), children: Vec::new() });
+        if points.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+            return index;
+        }
+        let middle = pos + Vec3::splat(0.5 * size);
+        let positions = self.positions;
+        let octant = |i: usize| positions[i].cmpge(middle).bitmask();
+        self.order[points.clone()].sort_by_key(|&i| octant(i));
+        let mut start = points.start;
+        for child in 0..8 {
+            let end = start + self.order[start..points.end].iter().take_while(|&&i| octant(i) == child).count();
+            if end > start {
+                let child = self.subdivide(Octree::idx_offset(middle, pos, child as u8), 0.5 * size, start..end, depth + 1);
+                self.cells[index].children.push(child);
+            }
+            start = end;
+        }
+        index
+    }
+    fn points(&self, cell: usize) -> &[usize] {
+        &self.order[self.cells[cell].points.clone()]
+    }
+    // Sorts cell pairs into far ones, handled through expansions, and near leaves summed directly
+    fn interact(&self, a: usize, b: usize, theta: f64, far: &mut [Vec<usize>], near: &mut [Vec<usize>]) {
+        let (ca, cb) = (&self.cells[a], &self.cells[b]);
+        if cb.mass == 0. {
+            return;
+        }
+        if a != b && ca.radius + cb.radius < theta * ca.center.distance(cb.center) {
+            far[a].push(b);
+        } else if ca.children.is_empty() && cb.children.is_empty() {
+            near[a].push(b);
+        } else if a == b {
+            for &x in &ca.children {
+                for &y in &ca.children {
+                    self.interact(x, y, theta, far, near);
+                }
+            }
+        } else if cb.children.is_empty() || (!ca.children.is_empty() && ca.radius >= cb.radius) {
+            for &x in &ca.children {
+                self.interact(x, b, theta, far, near);
+            }
+        } else {
+            for &y in &cb.children {
+                self.interact(a, y, theta, far, near);
+            }
+        }
+    }
+}
+
+// Gravitational acceleration at every point from the masses at all of them, by the fast
+// multipole method. Multipole and local expansions run up to `order` (at least 1), and cells
+// interact through them once their radii add up to less than `theta` times their separation.
+// Massless points feel the field without adding to it.
+pub fn accelerations(positions: &[Vec3], masses: &[f32], order: usize, theta: f32) -> Vec<Vec3> {
+    if positions.is_empty() {
+        return Vec::new();
+    }
+    let expansions = Expansions::new(order.max(1));
+    let indices = &expansions.indices;
+    let tree = Tree::new(positions, masses);
+    let cells = &tree.cells;
+
+    // Upward pass: multipoles of the leaves, shifted up into their parents
+    let mut multipoles = vec![vec![0.; indices.len()]; cells.len()];
+    for (k, cell) in cells.iter().enumerate().rev() {
+        let mut multipole = vec![0.; indices.len()];
+        if cell.children.is_empty() {
+            for &i in tree.points(k) {
+                let mass = masses[i] as f64;
+                for (m, x) in multipole.iter_mut().zip(indices.monomials(positions[i].as_dvec3() - cell.center)) {
+                    *m += mass * x;
+                }
+            }
+        } else {
+            for &child in &cell.children {
+                let shift = indices.monomials(cells[child].center - cell.center);
+                for &(alpha, beta, diff) in &expansions.shifts {
+                    multipole[alpha] += multipoles[child][beta] * shift[diff];
+                }
+            }
+        }
+        multipoles[k] = multipole;
+    }
+
+    let mut far = vec![Vec::new(); cells.len()];
+    let mut near = vec![Vec::new(); cells.len()];
+    tree.interact(0, 0, theta as f64, &mut far, &mut near);
+
+    // Far interactions, each cell summing its own list so the result doesn't depend on threads
+    let mut locals = par_map(cells.len(), 8, |a| {
+        let mut local = vec![0.; indices.len()];
+        let mut d = vec![0.; indices.len()];
+        for &b in &far[a] {
+            indices.derivatives(cells[a].center - cells[b].center, &mut d);
+            for &(beta, alpha, sum, sign) in &expansions.m2l {
+                local[beta] += sign * multipoles[b][alpha] * d[sum];
+            }
+        }
+        local
+    });
+    // Downward pass; children always come after their parent
+    for k in 0..cells.len() {
+        for &child in &cells[k].children {
+            let shift = indices.monomials(cells[child].center - cells[k].center);
+            let parent = locals[k].clone();
+            for &(gamma, beta, diff) in &expansions.shifts {
+                locals[child][beta] += parent[gamma] * shift[diff];
+            }
+        }
+    }
+
+    let leaves: Vec<usize> = (0..cells.len()).filter(|&k| cells[k].children.is_empty()).collect();
+    let per_leaf = par_map(leaves.len(), 4, |k| {
+        let leaf = leaves[k];
+        tree.points(leaf).iter().map(|&i| {
+            let pos = positions[i].as_dvec3();
+            let powers = indices.monomials(pos - cells[leaf].center);
+            let mut far_accel = DVec3::ZERO;
+            for &(beta, grad) in &expansions.gradient {
+                far_accel += powers[beta] * DVec3::new(locals[leaf][grad[0]], locals[leaf][grad[1]], locals[leaf][grad[2]]);
+            }
+            // Neighbours are summed in single precision like the direct sum
+            let mut accel = Vec3::ZERO;
+            for &source in &near[leaf] {
+                for &j in tree.points(source) {
+                    let d = positions[j] - positions[i];
+                    let len_squared = d.length_squared();
+                    if len_squared > 0. {
+                        accel += (masses[j] / (len_squared * len_squared.sqrt())) * d;
+                    }
+                }
+            }
+            (i, G * (accel + far_accel.as_vec3()))
+        }).collect::<Vec<_>>()
+    });
+    let mut accel = vec![Vec3::ZERO; positions.len()];
+    for (i, a) in per_leaf.into_iter().flatten() {
+        accel[i] = a;
+    }
+    accel
+}
+
+#[cfg(test)]
+mod fmm_tests {
+    use std::time::Instant;
+    use super::*;
+    use crate::body::Field;
+
+    // Plummer-like clump plus a uniform halo, from a fixed seed
+    fn cloud(n: usize) -> (Vec<Vec3>, Vec<f32>) {
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 40) as f32 / (1 << 24) as f32
+        };
+        let positions = (0..n).map(|i| {
+            let p = Vec3::new(next(), next(), next()) * 2. - Vec3::ONE;
+            if i % 2 == 0 { p * p.length_squared() } else { 3. * p }
+        }).collect();
+        let masses = (0..n).map(|_| (0.5 + next()) / G).collect();
+        (positions, masses)
+    }
+
+    fn direct(positions: &[Vec3], masses: &[f32]) -> Vec<Vec3> {
+        let states: Vec<_> = positions.iter().map(|p| (*p, Vec3::ZERO)).collect();
+        Field::new(&states, masses, None).source_accelerations()
+    }
+
+    fn relative_error(approx: &[Vec3], exact: &[Vec3]) -> f32 {
+        let error: f32 = approx.iter().zip(exact).map(|(a, e)| (*a - *e).length_squared()).sum();
+        let norm: f32 = exact.iter().map(|e| e.length_squared()).sum();
+        (error / norm).sqrt()
+    }
+
+    #[test]
+    fn converges_to_direct_sum() {
+        let (positions, masses) = cloud(3000);
+        let exact = direct(&positions, &masses);
+        let errors: Vec<f32> = [1, 2, 4, 6].iter()
+            .map(|&order| relative_error(&accelerations(&positions, &masses, order, 0.5), &exact))
+            .collect();
+        assert!(errors.windows(2).all(|pair| pair[1] < pair[0]), "{errors:?}");
+        assert!(errors[2] < 1e-3, "{errors:?}");
+        assert!(errors[3] < 1e-4, "{errors:?}");
+    }
+
+    #[test]
+    fn massless_points_feel_without_pulling() {
+        let (mut positions, mut masses) = cloud(500);
+        let exact = direct(&positions, &masses);
+        positions.extend((0..500).map(|i| Vec3::new(i as f32 / 100. - 2.5, 0.3, -0.2)));
+        masses.extend([0.; 500]);
+        let accel = accelerations(&positions, &masses, 6, 0.5);
+        assert!(relative_error(&accel[..500], &exact) < 1e-4);
+        let probe = accelerations(&[Vec3::ZERO, Vec3::X], &[0., 2. / G], 4, 0.5);
+        assert!((probe[0] - 2. * Vec3::X).length() < 1e-5);
+        assert_eq!(probe[1], Vec3::ZERO);
+    }
+
+    // Timing comparison; run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn benchmark_against_direct_sum() {
+        for n in [1000, 10000, 50000] {
+            let (positions, masses) = cloud(n);
+            let start = Instant::now();
+            let exact = direct(&positions, &masses);
+            let direct_time = start.elapsed();
+            let start = Instant::now();
+            let approx = accelerations(&positions, &masses, 4, 0.5);
+            let fmm_time = start.elapsed();
+            println!("{n} bodies: direct {direct_time:?}, fmm {fmm_time:?}, error {:.2e}", relative_error(&approx, &exact));
+            if n >= 50000 {
+                assert!(fmm_time < direct_time);
+            }
+        }
+    }
+}
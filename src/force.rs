@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+use std::{fmt, sync::Arc};
+
+// Everything a force can see at one integrator stage, indexed like the bodies
+pub struct Stage<'a> {
+    // Position and velocity of each body
+    pub states: &'a [(Vec3, Vec3)],
+    pub masses: &'a [f32],
+    // Luminosity of each body, zero for everything but stars
+    pub luminosities: &'a [f32],
+}
+
+// Non-gravitational physics. The integrator asks every force on a body for its acceleration
+// at each stage, on top of gravity.
+pub trait Force: Send + Sync + 'static {
+    fn acceleration(&self, body: usize, stage: &Stage) -> Vec3;
+}
+
+// Any closure of the right shape is a force
+impl<F: Fn(usize, &Stage) -> Vec3 + Send + Sync + 'static> Force for F {
+    fn acceleration(&self, body: usize, stage: &Stage) -> Vec3 {
+        self(body, stage)
+    }
+}
+
+// Forces acting on a body. They are shared rather than copied when a body is duplicated.
+#[derive(Component, Clone, Default)]
+pub struct Forces(pub Vec<Arc<dyn Force>>);
+impl Forces {
+    pub fn with(mut self, force: impl Force) -> Self {
+        self.0.push(Arc::new(force));
+        self
+    }
+    pub fn acceleration(&self, body: usize, stage: &Stage) -> Vec3 {
+        self.0.iter().map(|force| force.acceleration(body, stage)).sum()
+    }
+}
+impl fmt::Debug for Forces {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Forces({})", self.0.len())
+    }
+}
+
+// Fixed force in world coordinates, so the acceleration it gives falls as the body gets heavier
+#[derive(Clone, Copy, Debug)]
+pub struct ConstantThrust(pub Vec3);
+impl Force for ConstantThrust {
+    fn acceleration(&self, body: usize, stage: &Stage) -> Vec3 {
+        let mass = stage.masses[body];
+        if mass > 0. { self.0 / mass } else { Vec3::ZERO }
+    }
+}
+
+// Force of -coefficient·velocity
+#[derive(Clone, Copy, Debug)]
+pub struct LinearDrag(pub f32);
+impl Force for LinearDrag {
+    fn acceleration(&self, body: usize, stage: &Stage) -> Vec3 {
+        let mass = stage.masses[body];
+        if mass > 0. { -self.0 / mass * stage.states[body].1 } else { Vec3::ZERO }
+    }
+}
+
+// Push away from every star of coefficient·luminosity/r². The coefficient folds together
+// reflectivity, cross-section over mass and 1/(4πc).
+#[derive(Clone, Copy, Debug)]
+pub struct RadiationPressure(pub f32);
+impl Force for RadiationPressure {
+    fn acceleration(&self, body: usize, stage: &Stage) -> Vec3 {
+        let this = stage.states[body].0;
+        stage.states.iter().zip(stage.luminosities).map(|((star, _), luminosity)| {
+            let d = this - *star;
+            let len_squared = d.length_squared();
+            if *luminosity <= 0. || len_squared <= 0. {
+                return Vec3::ZERO;
+            }
+            self.0 * luminosity / (len_squared * len_squared.sqrt()) * d
+        }).sum()
+    }
+}
+
+#[cfg(test)]
+mod force_tests {
+    use super::*;
+
+    #[test]
+    fn forces_add_up() {
+        let states = [(Vec3::ZERO, Vec3::ZERO), (Vec3::new(2., 0., 0.), Vec3::new(0., 1., 0.))];
+        let stage = Stage { states: &states, masses: &[1., 0.5], luminosities: &[8., 0.] };
+        let forces = Forces::default()
+            .with(ConstantThrust(Vec3::Z))
+            .with(LinearDrag(0.25))
+            .with(RadiationPressure(0.5))
+            .with(|body: usize, stage: &Stage| stage.masses[body] * Vec3::NEG_Z);
+        // 2 up from thrust, 0.5 back from drag, 1 outward from the star and 0.5 down from the
+        // closure
+        assert_eq!(forces.acceleration(1, &stage), Vec3::new(1., -0.5, 1.5));
+        // Thrust and closure cancel on the unit mass
+        assert_eq!(forces.acceleration(0, &stage), Vec3::ZERO);
+        // Stars don't push on themselves
+        assert_eq!(RadiationPressure(1.).acceleration(0, &stage), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,186 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use crate::{body::{Body, BodyAdded}, input::{Action, action_just_pressed}, ring::RingParticle};
+
+// Frame the scene is viewed in. Bodies are parented to a `FrameRoot` whose transform maps
+// inertial coordinates into the chosen frame, so the physics state in each body's
+// `Transform` is never touched.
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq)]
+pub enum ViewFrame {
+    #[default]
+    Inertial,
+    Barycentric,
+    Body(Entity),
+    CoRotating(Entity, Entity),
+}
+
+#[derive(Component)]
+pub struct FrameRoot;
+
+fn setup_frame(mut commands: Commands) {
+    commands.spawn((SpatialBundle::default(), FrameRoot));
+}
+
+fn adopt_bodies(
+    mut commands: Commands,
+    root: Query<Entity, With<FrameRoot>>,
+    bodies: Query<Entity, (Added<Body>, Without<Parent>)>,
+) {
+    let Ok(root) = root.get_single() else { return };
+    for e in &bodies {
+        commands.entity(root).add_child(e);
+    }
+}
+
+// Bodies the frame can be attached to
+type Targets<'w, 's> = Query<'w, 's, (Entity, &'static Transform, &'static Body), Without<RingParticle>>;
+
+fn next_body(bodies: &Targets, current: Option<Entity>) -> Option<Entity> {
+    let mut it = bodies.iter().map(|(e, ..)| e).skip_while(|&e| Some(e) != current);
+    it.next();
+    it.next().or_else(|| bodies.iter().next().map(|(e, ..)| e))
+}
+
+fn describe(frame: ViewFrame, bodies: &Targets) -> String {
+    let name = |e| bodies.get(e).map_or("?", |(_, _, body)| body.name.as_str());
+    match frame {
+        ViewFrame::Inertial => String::from("inertial"),
+        ViewFrame::Barycentric => String::from("barycentric"),
+        ViewFrame::Body(e) => format!("centred on {}", name(e)),
+        ViewFrame::CoRotating(a, b) => format!("co-rotating with {} and {}", name(a), name(b)),
+    }
+}
+
+fn cycle_frame(mut frame: ResMut<ViewFrame>, bodies: Targets) {
+    let first = next_body(&bodies, None);
+    let second = first.and_then(|e| next_body(&bodies, Some(e))).filter(|&e| Some(e) != first);
+    *frame = match (*frame, first, second) {
+        (ViewFrame::Inertial, ..) => ViewFrame::Barycentric,
+        (ViewFrame::Barycentric, Some(a), _) => ViewFrame::Body(a),
+        (ViewFrame::Body(_), Some(a), Some(b)) => ViewFrame::CoRotating(a, b),
+        _ => ViewFrame::Inertial,
+    };
+    info!("View frame: {}", describe(*frame, &bodies));
+}
+
+fn cycle_target(mut frame: ResMut<ViewFrame>, bodies: Targets) {
+    *frame = match *frame {
+        ViewFrame::Body(e) => match next_body(&bodies, Some(e)) {
+            Some(e) => ViewFrame::Body(e),
+            None => ViewFrame::Inertial,
+        },
+        ViewFrame::CoRotating(a, b) => match next_body(&bodies, Some(b)) {
+            Some(n) if n == a => ViewFrame::CoRotating(a, next_body(&bodies, Some(n)).unwrap_or(b)),
+            Some(n) => ViewFrame::CoRotating(a, n),
+            None => ViewFrame::Inertial,
+        },
+        frame => frame,
+    };
+    info!("View frame: {}", describe(*frame, &bodies));
+}
+
+// Origin, its velocity, orientation and angular velocity of a view frame in inertial coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameState {
+    pub origin: Vec3,
+    pub vel: Vec3,
+    pub rotation: Quat,
+    pub omega: Vec3,
+}
+impl FrameState {
+    const INERTIAL: Self = Self { origin: Vec3::ZERO, vel: Vec3::ZERO, rotation: Quat::IDENTITY, omega: Vec3::ZERO };
+
+    // Transform taking inertial coordinates into the frame
+    pub fn transform(&self) -> Transform {
+        let rotation = self.rotation.inverse();
+        Transform { translation: -(rotation * self.origin), rotation, ..default() }
+    }
+    // Inertial position and velocity of a point seen at `pos` moving with `vel` in the frame
+    pub fn to_inertial(self, pos: Vec3, vel: Vec3) -> (Vec3, Vec3) {
+        let offset = self.rotation * pos;
+        (self.origin + offset, self.vel + self.omega.cross(offset) + self.rotation * vel)
+    }
+}
+
+// State of `frame`, or `None` if a body it refers to is gone
+pub fn frame_state(frame: ViewFrame, bodies: &Query<(Entity, &Transform, &Body)>) -> Option<FrameState> {
+    let translated = |origin, vel| FrameState { origin, vel, ..FrameState::INERTIAL };
+    match frame {
+        ViewFrame::Inertial => Some(FrameState::INERTIAL),
+        ViewFrame::Barycentric => {
+            let (sum, mom, mass) = bodies.iter().fold((Vec3::ZERO, Vec3::ZERO, 0.), |(sum, mom, mass), (_, trans, body)| {
+                (sum + body.mass * trans.translation, mom + body.mass * body.vel, mass + body.mass)
+            });
+            Some(if mass > 0. { translated(sum / mass, mom / mass) } else { FrameState::INERTIAL })
+        },
+        ViewFrame::Body(e) => {
+            let (_, trans, body) = bodies.get(e).ok()?;
+            Some(translated(trans.translation, body.vel))
+        },
+        ViewFrame::CoRotating(a, b) => {
+            let (_, trans_a, body_a) = bodies.get(a).ok()?;
+            let (_, trans_b, body_b) = bodies.get(b).ok()?;
+            let mass = body_a.mass + body_b.mass;
+            let (wa, wb) = if mass > 0. { (body_a.mass / mass, body_b.mass / mass) } else { (0.5, 0.5) };
+            let d = trans_b.translation - trans_a.translation;
+            let h = d.cross(body_b.vel - body_a.vel);
+            let x = d.try_normalize().unwrap_or(Vec3::X);
+            let z = h.try_normalize().unwrap_or_else(|| x.any_orthonormal_vector());
+            Some(FrameState {
+                origin: wa * trans_a.translation + wb * trans_b.translation,
+                vel: wa * body_a.vel + wb * body_b.vel,
+                rotation: Quat::from_mat3(&Mat3::from_cols(x, z.cross(x), z)),
+                omega: if d.length_squared() > 0. { h / d.length_squared() } else { Vec3::ZERO },
+            })
+        },
+    }
+}
+
+pub fn apply_frame(
+    mut frame: ResMut<ViewFrame>,
+    mut root: Query<&mut Transform, (With<FrameRoot>, Without<Body>)>,
+    bodies: Query<(Entity, &Transform, &Body)>,
+) {
+    let Ok(mut root) = root.get_single_mut() else { return };
+    match frame_state(*frame, &bodies) {
+        Some(state) => *root = state.transform(),
+        None => {
+            *frame = ViewFrame::Inertial;
+            *root = Transform::IDENTITY;
+        },
+    }
+}
+
+#[derive(Component)]
+pub struct FramePlugin;
+impl Plugin for FramePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ViewFrame>()
+            .add_systems(Startup, setup_frame)
+            .add_systems(Update, (
+                adopt_bodies.in_set(BodyAdded),
+                cycle_frame.run_if(action_just_pressed(Action::CycleFrame)),
+                cycle_target.run_if(action_just_pressed(Action::CycleTarget)),
+            ))
+            .add_systems(PostUpdate, apply_frame.before(TransformSystem::TransformPropagate));
+    }
+}
+
+#[cfg(test)]
+mod frame_tests {
+    use super::*;
+    #[test]
+    fn inertial_round_trip() {
+        let state = FrameState {
+            origin: Vec3::new(1., 2., 3.),
+            vel: Vec3::new(0.5, 0., 0.),
+            rotation: Quat::from_rotation_z(0.3),
+            omega: Vec3::new(0., 0., 0.3),
+        };
+        let p = Vec3::new(-0.5, 0.25, 2.);
+        let (pos, vel) = state.to_inertial(p, Vec3::ZERO);
+        assert!(state.transform().transform_point(pos).abs_diff_eq(p, 1e-5));
+        // A point at rest in a rotating frame moves with the rotation
+        let expected = state.vel + state.omega.cross(pos - state.origin);
+        assert!(vel.abs_diff_eq(expected, 1e-5));
+    }
+}
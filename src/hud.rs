@@ -0,0 +1,113 @@
+use bevy::{prelude::*, diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin}, ecs::query::Has};
+use crate::{body::{Body, Scenario, SimClock, TestParticle, total_energy}, input::{Action, action_just_pressed}};
+
+// Large type on a dark backing so the overlay stays legible in screen recordings
+const FONT_SIZE: f32 = 22.;
+const BACKGROUND: Color = Color::rgba(0., 0., 0., 0.6);
+// Energy is O(n²), so the slow statistics refresh a few times per second
+const REFRESH: f32 = 0.25;
+
+#[derive(Component)]
+struct HudText;
+
+#[derive(Resource)]
+struct HudStats {
+    timer: Timer,
+    last_steps: u64,
+    steps_per_sec: f32,
+    // Energy the drift is measured against, reset when bodies are added or removed and with
+    // the rest of the stats when a scenario loads
+    reference: Option<(usize, f64)>,
+    drift: f64,
+}
+impl Default for HudStats {
+    fn default() -> Self {
+        HudStats {
+            timer: Timer::from_seconds(REFRESH, TimerMode::Repeating),
+            last_steps: 0,
+            steps_per_sec: 0.,
+            reference: None,
+            drift: 0.,
+        }
+    }
+}
+
+fn setup_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font_size: FONT_SIZE, color: Color::WHITE, ..default() })
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                bottom: Val::Px(8.),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            })
+            .with_background_color(BACKGROUND),
+        HudText,
+    ));
+}
+
+fn toggle_hud(mut hud: Query<&mut Visibility, With<HudText>>) {
+    let mut vis = hud.single_mut();
+    *vis = match *vis {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => Visibility::Hidden,
+    };
+}
+
+fn reset_hud(mut stats: ResMut<HudStats>) {
+    *stats = HudStats::default();
+}
+
+fn update_hud(
+    mut stats: ResMut<HudStats>,
+    mut hud: Query<&mut Text, With<HudText>>,
+    real: Res<Time<Real>>,
+    virt: Res<Time<Virtual>>,
+    clock: Res<SimClock>,
+    diagnostics: Res<DiagnosticsStore>,
+    bodies: Query<(&Transform, &Body, Has<TestParticle>)>,
+) {
+    let count = bodies.iter().len();
+    if stats.timer.tick(real.delta()).just_finished() {
+        let elapsed = stats.timer.duration().as_secs_f32() * stats.timer.times_finished_this_tick() as f32;
+        stats.steps_per_sec = clock.steps.saturating_sub(stats.last_steps) as f32 / elapsed;
+        stats.last_steps = clock.steps;
+        // Test particles exert no gravity, so their energy isn't conserved with the rest
+        let state: Vec<_> = bodies.iter().filter(|b| !b.2).map(|(trans, body, _)| (trans.translation, body)).collect();
+        let energy = total_energy(&state);
+        let reference = match stats.reference {
+            Some((n, e0)) if n == state.len() => e0,
+            _ => energy,
+        };
+        stats.reference = Some((state.len(), reference));
+        stats.drift = if reference != 0. { (energy - reference) / reference.abs() } else { 0. };
+    }
+    let fps = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS).and_then(|fps| fps.smoothed()).unwrap_or(0.);
+    let mut text = hud.single_mut();
+    text.sections[0].value = format!(
+        "t = {:.3}{}\nwarp {}x\n{:.0} steps/s\n{count} bodies\n{fps:.0} fps\ndE/E0 = {:+.3e}",
+        clock.time,
+        if virt.is_paused() { "  [paused]" } else { "" },
+        virt.relative_speed(),
+        stats.steps_per_sec,
+        stats.drift,
+    );
+}
+
+#[derive(Component)]
+pub struct HudPlugin;
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        app.init_resource::<HudStats>()
+            .add_systems(Startup, setup_hud)
+            .add_systems(Update, (
+                toggle_hud.run_if(action_just_pressed(Action::ToggleHud)),
+                reset_hud.run_if(resource_changed::<Scenario>()),
+                update_hud,
+            ).chain());
+    }
+}
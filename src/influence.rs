@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use crate::{
+    body::{Body, BodyAdded, G, TestParticle},
+    frame::FrameRoot,
+    input::{Action, action_just_pressed},
+};
+
+const HILL_COLOR: Color = Color::CYAN;
+const SOI_COLOR: Color = Color::ORANGE;
+
+// Dominant primary of a body with its Hill radius and Laplace sphere of influence. The most
+// massive body has no primary and infinite radii.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Influence {
+    pub primary: Option<Entity>,
+    pub hill_radius: f32,
+    pub soi_radius: f32,
+}
+impl Default for Influence {
+    fn default() -> Self {
+        Influence { primary: None, hill_radius: f32::INFINITY, soi_radius: f32::INFINITY }
+    }
+}
+
+#[derive(Resource, Default)]
+struct InfluenceOverlay(bool);
+
+// Semi-major axis and eccentricity of `m` orbiting `primary_mass` at relative position `r` and
+// velocity `v`. Unbound orbits count as circular at the current distance.
+fn orbit(r: Vec3, v: Vec3, primary_mass: f32, m: f32) -> (f32, f32) {
+    let mu = G * (primary_mass + m);
+    let energy = 0.5 * v.length_squared() - mu / r.length();
+    if energy >= 0. {
+        return (r.length(), 0.);
+    }
+    let h = r.cross(v).length();
+    (-mu / (2. * energy), (1. + 2. * energy * h * h / (mu * mu)).max(0.).sqrt())
+}
+
+// Primary index, Hill radius and sphere of influence for each (position, velocity, mass).
+// Bodies are placed from the heaviest down, each orbiting the innermost heavier body whose
+// sphere of influence contains it.
+pub fn influences(bodies: &[(Vec3, Vec3, f32)]) -> Vec<(Option<usize>, f32, f32)> {
+    let mut order: Vec<usize> = (0..bodies.len()).collect();
+    order.sort_by(|&a, &b| bodies[b].2.total_cmp(&bodies[a].2));
+    let mut result = vec![(None, f32::INFINITY, f32::INFINITY); bodies.len()];
+    for (rank, &i) in order.iter().enumerate() {
+        let (pos, vel, mass) = bodies[i];
+        let primary = order[..rank].iter().copied()
+            .filter(|&j| pos.distance(bodies[j].0) < result[j].2)
+            .min_by(|&a, &b| result[a].2.total_cmp(&result[b].2));
+        let Some(p) = primary else { continue };
+        let (p_pos, p_vel, p_mass) = bodies[p];
+        let (a, e) = orbit(pos - p_pos, vel - p_vel, p_mass, mass);
+        let ratio = if p_mass > 0. { mass / p_mass } else { 0. };
+        result[i] = (Some(p), a * (1. - e) * (ratio / 3.).cbrt(), a * ratio.powf(0.4));
+    }
+    result
+}
+
+// Test particles don't pull on anything, and there can be too many to rank every frame
+fn add_influence(mut commands: Commands, bodies: Query<Entity, (Added<Body>, Without<TestParticle>)>) {
+    for e in &bodies {
+        commands.entity(e).insert(Influence::default());
+    }
+}
+
+fn update_influence(mut bodies: Query<(Entity, &Transform, &Body, &mut Influence)>) {
+    let entities: Vec<Entity> = bodies.iter().map(|(e, ..)| e).collect();
+    let state: Vec<_> = bodies.iter().map(|(_, trans, body, _)| (trans.translation, body.vel, body.mass)).collect();
+    for ((primary, hill_radius, soi_radius), (.., mut influence)) in influences(&state).into_iter().zip(&mut bodies) {
+        let new = Influence { primary: primary.map(|p| entities[p]), hill_radius, soi_radius };
+        if *influence != new {
+            *influence = new;
+        }
+    }
+}
+
+fn toggle_overlay(mut overlay: ResMut<InfluenceOverlay>) {
+    overlay.0 = !overlay.0;
+}
+
+fn draw_overlay(
+    overlay: Res<InfluenceOverlay>,
+    mut gizmos: Gizmos,
+    root: Query<&Transform, With<FrameRoot>>,
+    bodies: Query<(&Transform, &Influence), Without<TestParticle>>,
+) {
+    if !overlay.0 {
+        return;
+    }
+    let root = root.get_single().copied().unwrap_or_default();
+    for (trans, influence) in &bodies {
+        if influence.primary.is_none() {
+            continue;
+        }
+        let center = root.transform_point(trans.translation);
+        gizmos.sphere(center, Quat::IDENTITY, influence.hill_radius, HILL_COLOR);
+        gizmos.sphere(center, Quat::IDENTITY, influence.soi_radius, SOI_COLOR);
+    }
+}
+
+#[derive(Component)]
+pub struct InfluencePlugin;
+impl Plugin for InfluencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InfluenceOverlay>()
+            .add_systems(Update, (
+                add_influence.in_set(BodyAdded),
+                update_influence,
+                toggle_overlay.run_if(action_just_pressed(Action::ToggleInfluence)),
+                draw_overlay,
+            ).chain());
+    }
+}
+
+#[cfg(test)]
+mod influence_tests {
+    use super::*;
+
+    #[test]
+    fn sun_earth_moon() {
+        // GM of 1, 0.01 and 1e-4 with Earth on a circular orbit at 1 and the Moon at 0.06 from it
+        let (sun, earth, moon) = (1. / G, 0.01 / G, 1e-4 / G);
+        let v_earth = (1.01_f32).sqrt();
+        let v_moon = v_earth + (0.0101_f32 / 0.06).sqrt();
+        let bodies = [
+            (Vec3::new(1.06, 0., 0.), Vec3::new(0., v_moon, 0.), moon),
+            (Vec3::ZERO, Vec3::ZERO, sun),
+            (Vec3::X, Vec3::new(0., v_earth, 0.), earth),
+        ];
+        let result = influences(&bodies);
+        assert_eq!(result[1].0, None);
+        assert_eq!(result[2].0, Some(1));
+        assert_eq!(result[0].0, Some(2));
+        // Circular orbits: r_H = a·(m/3M)^(1/3), r_SOI = a·(m/M)^(2/5)
+        assert!((result[2].1 - (0.01_f32 / 3.).cbrt()).abs() < 1e-3);
+        assert!((result[2].2 - 0.01_f32.powf(0.4)).abs() < 1e-3);
+        assert!((result[0].2 - 0.06 * 0.01_f32.powf(0.4)).abs() < 1e-4);
+    }
+}
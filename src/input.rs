@@ -0,0 +1,336 @@
+use bevy::{prelude::*, ecs::system::SystemParam, reflect::{DynamicEnum, DynamicVariant, TypeInfo, Typed}, utils::HashMap};
+use toml::Value;
+use std::{fs, path::Path};
+use crate::body::Scenario;
+
+// Global bindings, used by scenarios without an input sidecar of their own
+const FILE: &str = "assets/input.toml";
+const SIDECAR: &str = "input";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    RollLeft,
+    RollRight,
+    Orbit,
+    Pan,
+    Pause,
+    WarpUp,
+    WarpDown,
+    CycleFocus,
+    SaveBookmark,
+    Bookmark1,
+    Bookmark2,
+    Bookmark3,
+    Bookmark4,
+    Bookmark5,
+    Bookmark6,
+    Bookmark7,
+    Bookmark8,
+    Bookmark9,
+    SplitView,
+    ToggleEditor,
+    ToggleHud,
+    ToggleLabels,
+    LabelDetails,
+    ToggleTimeline,
+    StepBack,
+    StepForward,
+    TogglePlots,
+    PlotSelected,
+    ExportPlots,
+    TogglePlayback,
+    RecordTrajectory,
+    SeekBack,
+    SeekForward,
+    ToggleScenarios,
+    BurnPrograde,
+    BurnRetrograde,
+    ToggleInfluence,
+    ToggleSpawn,
+    CyclePreset,
+    PlaceBody,
+    DeleteBody,
+    DuplicateBody,
+    CycleFrame,
+    CycleTarget,
+}
+
+// Config name and default bindings of every action
+const ACTIONS: [(Action, &str, &[&str]); 50] = [
+    (Action::Forward, "forward", &["W"]),
+    (Action::Back, "back", &["S"]),
+    (Action::Left, "left", &["A"]),
+    (Action::Right, "right", &["D"]),
+    (Action::Up, "up", &["ShiftLeft"]),
+    (Action::Down, "down", &["ControlLeft"]),
+    (Action::RollLeft, "roll_left", &["Q"]),
+    (Action::RollRight, "roll_right", &["E"]),
+    (Action::Orbit, "orbit", &["MouseRight"]),
+    (Action::Pan, "pan", &["MouseMiddle"]),
+    (Action::Pause, "pause", &["Space", "PadStart"]),
+    (Action::WarpUp, "warp_up", &["Period", "PadRightTrigger"]),
+    (Action::WarpDown, "warp_down", &["Comma", "PadLeftTrigger"]),
+    (Action::CycleFocus, "cycle_focus", &["C", "PadNorth"]),
+    (Action::SaveBookmark, "save_bookmark", &["AltLeft"]),
+    (Action::Bookmark1, "bookmark_1", &["Key1"]),
+    (Action::Bookmark2, "bookmark_2", &["Key2"]),
+    (Action::Bookmark3, "bookmark_3", &["Key3"]),
+    (Action::Bookmark4, "bookmark_4", &["Key4"]),
+    (Action::Bookmark5, "bookmark_5", &["Key5"]),
+    (Action::Bookmark6, "bookmark_6", &["Key6"]),
+    (Action::Bookmark7, "bookmark_7", &["Key7"]),
+    (Action::Bookmark8, "bookmark_8", &["Key8"]),
+    (Action::Bookmark9, "bookmark_9", &["Key9"]),
+    (Action::SplitView, "split_view", &["V"]),
+    (Action::ToggleEditor, "toggle_editor", &["F1"]),
+    (Action::ToggleHud, "toggle_hud", &["H"]),
+    (Action::ToggleLabels, "toggle_labels", &["L"]),
+    (Action::LabelDetails, "label_details", &["K"]),
+    (Action::ToggleTimeline, "toggle_timeline", &["T"]),
+    (Action::StepBack, "step_back", &["BracketLeft", "PadDPadLeft"]),
+    (Action::StepForward, "step_forward", &["BracketRight", "PadDPadRight"]),
+    (Action::TogglePlots, "toggle_plots", &["G"]),
+    (Action::PlotSelected, "plot_selected", &["J"]),
+    (Action::ExportPlots, "export_plots", &["F5"]),
+    (Action::TogglePlayback, "toggle_playback", &["O"]),
+    (Action::RecordTrajectory, "record_trajectory", &["F6"]),
+    (Action::SeekBack, "seek_back", &["Minus"]),
+    (Action::SeekForward, "seek_forward", &["Equals"]),
+    (Action::ToggleScenarios, "toggle_scenarios", &["M"]),
+    (Action::BurnPrograde, "burn_prograde", &["B", "PadEast"]),
+    (Action::BurnRetrograde, "burn_retrograde", &["X", "PadWest"]),
+    (Action::ToggleInfluence, "toggle_influence", &["I"]),
+    (Action::ToggleSpawn, "toggle_spawn", &["N"]),
+    (Action::CyclePreset, "cycle_preset", &["P"]),
+    (Action::PlaceBody, "place_body", &["MouseLeft"]),
+    (Action::DeleteBody, "delete_body", &["Delete"]),
+    (Action::DuplicateBody, "duplicate_body", &["Insert"]),
+    (Action::CycleFrame, "cycle_frame", &["F"]),
+    (Action::CycleTarget, "cycle_target", &["Tab"]),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+}
+impl InputButton {
+    // Parses a key name as spelled in `KeyCode` ("W", "ShiftLeft", "Key1"), a mouse button
+    // prefixed with "Mouse" ("MouseRight") or a gamepad button prefixed with "Pad" ("PadSouth")
+    fn parse(name: &str) -> Option<InputButton> {
+        if let Some(button) = name.strip_prefix("Mouse") {
+            unit_variant::<MouseButton>(button).map(InputButton::Mouse)
+        } else if let Some(button) = name.strip_prefix("Pad") {
+            unit_variant::<GamepadButtonType>(button).map(InputButton::Pad)
+        } else {
+            unit_variant::<KeyCode>(name).map(InputButton::Key)
+        }
+    }
+    fn pressed(&self, actions: &Actions) -> bool {
+        match *self {
+            InputButton::Key(k) => !actions.typing.0 && actions.keys.pressed(k),
+            InputButton::Mouse(b) => actions.mouse.pressed(b),
+            InputButton::Pad(b) => actions.gamepads.iter().any(|g| actions.pads.pressed(GamepadButton::new(g, b))),
+        }
+    }
+    fn just_pressed(&self, actions: &Actions) -> bool {
+        match *self {
+            InputButton::Key(k) => !actions.typing.0 && actions.keys.just_pressed(k),
+            InputButton::Mouse(b) => actions.mouse.just_pressed(b),
+            InputButton::Pad(b) => actions.gamepads.iter().any(|g| actions.pads.just_pressed(GamepadButton::new(g, b))),
+        }
+    }
+}
+
+// `from_reflect` panics on unknown variant names, so check the type info first
+fn unit_variant<T: FromReflect + Typed>(name: &str) -> Option<T> {
+    match T::type_info() {
+        TypeInfo::Enum(info) if info.contains_variant(name) =>
+            T::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit)),
+        _ => None,
+    }
+}
+
+// All buttons of a chord such as "AltLeft+MouseLeft" have to be held together
+type Binding = Vec<InputButton>;
+
+fn parse_binding(chord: &str) -> Option<Binding> {
+    chord.split('+').map(|name| InputButton::parse(name.trim())).collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Sensitivity {
+    pub translate: f32,
+    pub spin: f32,
+    pub orbit: f32,
+    pub pan: f32,
+    pub zoom: f32,
+    // Stick deflection and trigger pull per second, in mouse pixels and scroll lines
+    pub stick: f32,
+    pub trigger: f32,
+}
+impl Default for Sensitivity {
+    fn default() -> Self {
+        Sensitivity { translate: 1.2, spin: 1.2, orbit: 1e-2, pan: 5e-3, zoom: 0.2, stick: 300., trigger: 10. }
+    }
+}
+
+#[derive(Resource)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+    pub sensitivity: Sensitivity,
+}
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = ACTIONS.iter().map(|(action, _, chords)| {
+            (*action, chords.iter().filter_map(|chord| parse_binding(chord)).collect())
+        }).collect();
+        InputMap { bindings, sensitivity: default() }
+    }
+}
+impl InputMap {
+    // Actions or sensitivities missing from the file keep their defaults
+    pub fn load(path: &str) -> InputMap {
+        let mut map = InputMap::default();
+        let Ok(text) = fs::read_to_string(path) else {
+            info!("No input map at {path}, using default bindings");
+            return map;
+        };
+        let config: Value = match toml::from_str(text.as_str()) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring malformed input map {path}: {e}");
+                return map;
+            },
+        };
+        if let Some(bindings) = config.get("bindings").and_then(Value::as_table) {
+            for (action, name, _) in ACTIONS {
+                let Some(chords) = bindings.get(name).and_then(Value::as_array) else { continue };
+                let parsed = chords.iter().filter_map(|chord| {
+                    let binding = chord.as_str().and_then(parse_binding);
+                    if binding.is_none() {
+                        warn!("Unknown binding {chord} for {name}");
+                    }
+                    binding
+                }).collect();
+                map.bindings.insert(action, parsed);
+            }
+        }
+        if let Some(sens) = config.get("sensitivity").and_then(Value::as_table) {
+            let get_f = |key, default: f32| sens.get(key).and_then(Value::as_float).map_or(default, |f| f as f32);
+            let s = &mut map.sensitivity;
+            s.translate = get_f("translate", s.translate);
+            s.spin = get_f("spin", s.spin);
+            s.orbit = get_f("orbit", s.orbit);
+            s.pan = get_f("pan", s.pan);
+            s.zoom = get_f("zoom", s.zoom);
+            s.stick = get_f("stick", s.stick);
+            s.trigger = get_f("trigger", s.trigger);
+        }
+        map
+    }
+    // The scenario's own input map if it has one, otherwise the global file
+    pub fn for_scenario(scenario: &Scenario) -> InputMap {
+        let sidecar = scenario.sidecar(SIDECAR);
+        InputMap::load(if Path::new(&sidecar).exists() { &sidecar } else { FILE })
+    }
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+}
+
+// Set while a text field has keyboard focus, so typing doesn't trigger key bindings
+#[derive(Resource, Default)]
+pub struct TextEntry(pub bool);
+
+pub fn typing(entry: Res<TextEntry>) -> bool {
+    entry.0
+}
+
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    map: Res<'w, InputMap>,
+    typing: Res<'w, TextEntry>,
+    keys: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    pads: Res<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+}
+impl Actions<'_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.map.bindings(action).iter()
+            .any(|chord| chord.iter().all(|b| b.pressed(self)))
+    }
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.map.bindings(action).iter().any(|chord| {
+            chord.iter().all(|b| b.pressed(self)) && chord.iter().any(|b| b.just_pressed(self))
+        })
+    }
+    pub fn sensitivity(&self) -> &Sensitivity {
+        &self.map.sensitivity
+    }
+}
+
+fn reload_input_map(mut map: ResMut<InputMap>, scenario: Res<Scenario>) {
+    *map = InputMap::for_scenario(&scenario);
+}
+
+pub fn action_just_pressed(action: Action) -> impl FnMut(Actions) -> bool + Clone {
+    move |actions: Actions| actions.just_pressed(action)
+}
+
+#[derive(Component)]
+pub struct InputMapPlugin;
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<TextEntry>()
+            // Ahead of Update, so the bindings are in place before anything reads them
+            .add_systems(PreUpdate, reload_input_map.run_if(resource_changed::<Scenario>()));
+    }
+}
+
+#[cfg(test)]
+mod input_tests {
+    use super::*;
+    #[test]
+    fn parse_chords() {
+        assert_eq!(parse_binding("W"), Some(vec![InputButton::Key(KeyCode::W)]));
+        assert_eq!(
+            parse_binding("AltLeft + MouseLeft"),
+            Some(vec![InputButton::Key(KeyCode::AltLeft), InputButton::Mouse(MouseButton::Left)])
+        );
+        assert_eq!(parse_binding("NotAKey"), None);
+        assert_eq!(parse_binding("MouseSide"), None);
+        assert_eq!(parse_binding("PadStart"), Some(vec![InputButton::Pad(GamepadButtonType::Start)]));
+    }
+    #[test]
+    fn shipped_map_matches_defaults() {
+        let shipped = InputMap::load(FILE);
+        let default = InputMap::default();
+        for (action, ..) in ACTIONS {
+            assert_eq!(shipped.bindings(action), default.bindings(action));
+        }
+    }
+    #[test]
+    fn scenario_sidecar_overrides_global_map() {
+        let dir = std::env::temp_dir().join("colonize_input_tests");
+        fs::create_dir_all(&dir).unwrap();
+        let scenario = |name: &str| Scenario(dir.join(name).to_str().unwrap().to_owned());
+        let custom = scenario("custom.toml");
+        fs::write(custom.sidecar(SIDECAR), "[bindings]\npause = [\"P\"]\n").unwrap();
+        let map = InputMap::for_scenario(&custom);
+        assert_eq!(map.bindings(Action::Pause), [vec![InputButton::Key(KeyCode::P)]]);
+        assert_eq!(map.bindings(Action::Forward), [vec![InputButton::Key(KeyCode::W)]]);
+        // Without a sidecar the global file applies
+        let plain = InputMap::for_scenario(&scenario("plain.toml"));
+        assert_eq!(plain.bindings(Action::Pause), InputMap::load(FILE).bindings(Action::Pause));
+    }
+}
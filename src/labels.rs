@@ -0,0 +1,118 @@
+use bevy::{prelude::*, ui::UiSystem};
+use crate::{
+    body::{Body, TestParticle},
+    camera::{CenterCam, follow_focus},
+    frame::FrameRoot,
+    input::{Action, action_just_pressed},
+};
+
+const FONT_SIZE: f32 = 16.;
+// Opacity change per second while a label fades in or out
+const FADE_SPEED: f32 = 4.;
+// Gap between the body's projected centre and its label
+const LABEL_OFFSET: Vec2 = Vec2::new(8., -8.);
+
+#[derive(Resource)]
+struct LabelSettings {
+    show: bool,
+    details: bool,
+}
+impl Default for LabelSettings {
+    fn default() -> Self {
+        LabelSettings { show: true, details: false }
+    }
+}
+
+#[derive(Component)]
+struct BodyLabel {
+    body: Entity,
+    alpha: f32,
+}
+
+fn toggle_labels(mut settings: ResMut<LabelSettings>) {
+    settings.show = !settings.show;
+}
+
+fn toggle_details(mut settings: ResMut<LabelSettings>) {
+    settings.details = !settings.details;
+}
+
+fn spawn_labels(mut commands: Commands, bodies: Query<Entity, (Added<Body>, Without<TestParticle>)>) {
+    for body in &bodies {
+        commands.spawn((
+            TextBundle::from_section("", TextStyle { font_size: FONT_SIZE, color: Color::NONE, ..default() })
+                .with_style(Style { position_type: PositionType::Absolute, ..default() }),
+            BodyLabel { body, alpha: 0. },
+        ));
+    }
+}
+
+fn despawn_labels(mut commands: Commands, labels: Query<(Entity, &BodyLabel)>, bodies: Query<(), With<Body>>) {
+    for (e, label) in &labels {
+        if bodies.get(label.body).is_err() {
+            commands.entity(e).despawn_recursive();
+        }
+    }
+}
+
+// Places labels at the projected body positions. Nearer bodies win where labels would overlap
+// and the rest fade out, as do labels of bodies behind the camera.
+fn place_labels(
+    settings: Res<LabelSettings>,
+    time: Res<Time<Real>>,
+    root: Query<&Transform, With<FrameRoot>>,
+    cam: Query<(&Camera, &Transform), With<CenterCam>>,
+    bodies: Query<(&Transform, &Body)>,
+    mut labels: Query<(&mut BodyLabel, &mut Style, &mut Text, &Node)>,
+) {
+    let Ok((camera, cam_trans)) = cam.get_single() else { return };
+    let cam_global = GlobalTransform::from(*cam_trans);
+    let root = root.get_single().copied().unwrap_or_default();
+    let mut placed: Vec<_> = labels.iter_mut().filter_map(|label| {
+        let (trans, body) = bodies.get(label.0.body).ok()?;
+        let pos = root.transform_point(trans.translation);
+        let depth = cam_trans.forward().dot(pos - cam_trans.translation);
+        let screen = (depth > 0.).then(|| camera.world_to_viewport(&cam_global, pos)).flatten();
+        Some((depth, screen, cam_trans.translation.distance(pos), body.vel.length(), &body.name, label))
+    }).collect();
+    placed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut taken: Vec<Rect> = Vec::new();
+    for (_, screen, distance, speed, name, (mut label, mut style, mut text, node)) in placed {
+        let visible = screen.is_some_and(|screen| {
+            let corner = screen + LABEL_OFFSET - Vec2::new(0., node.size().y);
+            let rect = Rect::from_corners(corner, corner + node.size());
+            style.left = Val::Px(corner.x);
+            style.top = Val::Px(corner.y);
+            let free = taken.iter().all(|other| other.intersect(rect).is_empty());
+            if free {
+                taken.push(rect);
+            }
+            free
+        });
+        let target = if settings.show && visible { 1. } else { 0. };
+        let step = FADE_SPEED * time.delta_seconds();
+        label.alpha = if label.alpha < target { (label.alpha + step).min(target) } else { (label.alpha - step).max(target) };
+        text.sections[0].value = if settings.details {
+            format!("{name}\n{distance:.2} away, {speed:.3} speed")
+        } else {
+            name.clone()
+        };
+        text.sections[0].style.color = Color::WHITE.with_a(label.alpha);
+    }
+}
+
+#[derive(Component)]
+pub struct LabelPlugin;
+impl Plugin for LabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LabelSettings>()
+            .add_systems(Update, (
+                toggle_labels.run_if(action_just_pressed(Action::ToggleLabels)),
+                toggle_details.run_if(action_just_pressed(Action::LabelDetails)),
+                spawn_labels,
+                despawn_labels,
+            ))
+            .add_systems(PostUpdate, place_labels.after(follow_focus).before(UiSystem::Layout));
+    }
+}
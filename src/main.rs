@@ -1,22 +1,44 @@
-use bevy::{prelude::*, input::common_conditions::input_just_pressed};
+use bevy::prelude::*;
 #[cfg(feature = "inspector")]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use std::time::Duration;
 mod body;
+mod bookmark;
 mod camera;
+mod editor;
+mod fmm;
+mod force;
+mod frame;
+mod hud;
+mod influence;
+mod input;
+mod labels;
 mod octree;
+mod playback;
+mod ring;
+mod roche;
+mod plot;
+mod scenario;
+mod spacecraft;
+mod spawn;
+mod star;
+mod timeline;
+mod viewport;
 
 fn setup(mut commands: Commands) {
-    // light
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 1500.0,
-            shadows_enabled: true,
+    // light, hidden while stars light the scene
+    commands.spawn((
+        PointLightBundle {
+            point_light: PointLight {
+                intensity: 1500.0,
+                shadows_enabled: true,
+                ..default()
+            },
+            transform: Transform::from_xyz(4., 8., 4.),
             ..default()
         },
-        transform: Transform::from_xyz(4., 8., 4.),
-        ..default()
-    });
+        star::FixedLight,
+    ));
 }
 
 fn toggle_pause(mut time: ResMut<Time<Virtual>>) {
@@ -27,6 +49,15 @@ fn toggle_pause(mut time: ResMut<Time<Virtual>>) {
     }
 }
 
+const MAX_WARP: f32 = 64.;
+fn warp(factor: f32) -> impl FnMut(ResMut<Time<Virtual>>) {
+    move |mut time: ResMut<Time<Virtual>>| {
+        let speed = (factor * time.relative_speed()).clamp(1. / MAX_WARP, MAX_WARP);
+        time.set_relative_speed(speed);
+        info!("Time warp: {speed}x");
+    }
+}
+
 fn main() {
     let mut app = App::new();
     app.add_plugins((
@@ -38,13 +69,35 @@ fn main() {
                 ..default()
             })
         .set(ImagePlugin::default_nearest()),
+        input::InputMapPlugin,
         camera::CameraPlugin,
+        bookmark::BookmarkPlugin,
+        viewport::ViewportPlugin,
         body::BodyPlugin,
+        frame::FramePlugin,
+        editor::EditorPlugin,
+        spawn::SpawnPlugin,
+        hud::HudPlugin,
+        labels::LabelPlugin,
+        timeline::TimelinePlugin,
+        plot::PlotPlugin,
+        playback::PlaybackPlugin,
+        scenario::ScenarioPlugin,
+    ));
+    app.add_plugins((
+        spacecraft::SpacecraftPlugin,
+        roche::RochePlugin,
+        star::StarPlugin,
+        influence::InfluencePlugin,
     ));
     #[cfg(feature = "inspector")]
     app.add_plugins(WorldInspectorPlugin::new());
     app.insert_resource(Time::<Fixed>::from_duration(Duration::from_micros(15625)))
        .add_systems(Startup, setup)
-       .add_systems(Update, toggle_pause.run_if(input_just_pressed(KeyCode::Space)));
+       .add_systems(Update, (
+           toggle_pause.run_if(input::action_just_pressed(input::Action::Pause)),
+           warp(2.).run_if(input::action_just_pressed(input::Action::WarpUp)),
+           warp(0.5).run_if(input::action_just_pressed(input::Action::WarpDown)),
+       ));
     app.run();
 }
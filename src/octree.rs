@@ -19,12 +19,13 @@ pub enum OctNode {
 }
 
 impl Octree {
-    fn idx_offset(a: Vec3, b: Vec3, i: u8) -> Vec3 {
+    pub(crate) fn idx_offset(a: Vec3, b: Vec3, i: u8) -> Vec3 {
         Vec3::select(
             BVec3::new(i & 1 != 0, i >> 1 & 1 != 0, i >> 2 & 1 != 0),
             a, b
         )
     }
+    #[allow(dead_code)]
     pub fn leaf_unchecked(pos: Vec3, size: f32, new: Entity) -> Octree {
         Octree { pos, size, node: OctNode::Leaf(new) }
     }
@@ -117,6 +118,12 @@ impl Octree {
             }
         }
     }
+    pub fn rebuild(&mut self, query: &Query<(Entity, &Transform, &Body)>) {
+        *self = Octree::empty(self.pos, self.size);
+        for (e, ..) in query {
+            self.add(query, e);
+        }
+    }
     pub fn add(&mut self, query: &Query<(Entity, &Transform, &Body)>, new: Entity) {
         if let Ok((_, trans, _)) = query.get(new) {
             if self.contains(trans.translation) {
@@ -128,6 +135,20 @@ impl Octree {
     }
 }
 
+// Leaves refer to body entities, so the tree is rebuilt whenever bodies are spawned or despawned
+pub fn sync_octree(
+    tree: Option<ResMut<Octree>>,
+    mut removed: RemovedComponents<Body>,
+    added: Query<(), Added<Body>>,
+    query: Query<(Entity, &Transform, &Body)>,
+) {
+    let changed = removed.read().count() > 0 || !added.is_empty();
+    if let (Some(mut tree), true) = (tree, changed) {
+        tree.rebuild(&query);
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Default, Clone, Copy)]
 pub struct COM {
     sum: Vec3,
@@ -138,6 +159,7 @@ impl COM {
     fn new(p1: Vec3, m1: f32, p2: Vec3, m2: f32) -> COM {
         COM { sum: m1 * p1 + m2 * p2, mass: m1 + m2 }
     }
+    #[allow(dead_code)]
     fn com(&self) -> (Vec3, f32) {
         (self.sum / self.mass, self.mass)
     }
@@ -198,8 +220,10 @@ mod octree_tests {
             Body {
                 name: "Test".to_owned(),
                 mass: m,
+                radius: 1.,
                 vel: Vec3::ZERO,
                 angular_vel: Vec3::ZERO,
+                inertia: None,
             },
             Transform::from_translation(pos)
         )
@@ -408,4 +432,19 @@ mod octree_tests {
         };
         assert_eq!(*app.world.resource::<Octree>(), answer);
     }
+    #[test]
+    fn sync_after_despawn() {
+        let mut app = App::new();
+        let entities = [
+            app.world.spawn(build_body(Vec3::new(0.5, 0., 0.), 1.)).id(),
+            app.world.spawn(build_body(Vec3::new(0., 0.5, 0.), 1.)).id(),
+        ];
+        app.insert_resource(Octree::empty(Vec3::ZERO, 1.))
+            .add_systems(Update, sync_octree);
+        app.update();
+        app.world.despawn(entities[1]);
+        app.update();
+        let answer = Octree::leaf_unchecked(Vec3::ZERO, 1., entities[0]);
+        assert_eq!(*app.world.resource::<Octree>(), answer);
+    }
 }
@@ -0,0 +1,295 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+use std::{collections::{HashMap, HashSet}, fs};
+use crate::{
+    body::{Body, BodySpawner, History, HistoryBodies, Physics, Respawn, Scenario, SimClock, Snapshot, restore_snapshot},
+    input::{Action, action_just_pressed},
+    ring::RingParticle,
+};
+
+// One row per body and sample: name, time, position xyz, rotation quaternion xyzw
+const FILE: &str = "assets/trajectory.csv";
+const HEADER: &str = "body,time,x,y,z,qx,qy,qz,qw";
+// Fraction of the recording skipped per seek
+const SEEK_STEP: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    time: f64,
+    position: Vec3,
+    rotation: Quat,
+}
+
+#[derive(Default, Debug)]
+pub struct Trajectory {
+    tracks: HashMap<String, Vec<Sample>>,
+    start: f64,
+    end: f64,
+}
+// First name given to more than one body, which would make tracks ambiguous
+fn duplicate_name<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    names.into_iter().find(|name| !seen.insert(*name))
+}
+
+// Splits off the leading name field, which may be quoted with `""` escaping quotes inside
+fn split_name(line: &str) -> Option<(String, &str)> {
+    let Some(quoted) = line.strip_prefix('"') else {
+        let (name, rest) = line.split_once(',')?;
+        return Some((name.trim().to_owned(), rest));
+    };
+    let mut name = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c != '"' {
+            name.push(c);
+        } else if quoted[i + 1..].starts_with('"') {
+            name.push('"');
+            chars.next();
+        } else {
+            return Some((name, quoted[i + 1..].strip_prefix(',')?));
+        }
+    }
+    None
+}
+
+impl Trajectory {
+    // Fails if two bodies of the same name were recorded, as their samples can't be told apart
+    pub fn parse(text: &str) -> Result<Trajectory, String> {
+        let mut tracks: HashMap<String, Vec<Sample>> = HashMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line == HEADER {
+                continue;
+            }
+            let Some((name, rest)) = split_name(line) else {
+                warn!("Skipping malformed trajectory line {}", n + 1);
+                continue;
+            };
+            let values: Vec<f64> = rest.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+            let &[time, x, y, z, qx, qy, qz, qw] = values.as_slice() else {
+                warn!("Skipping malformed trajectory line {}", n + 1);
+                continue;
+            };
+            tracks.entry(name).or_default().push(Sample {
+                time,
+                position: Vec3::new(x as f32, y as f32, z as f32),
+                rotation: Quat::from_xyzw(qx as f32, qy as f32, qz as f32, qw as f32).normalize(),
+            });
+        }
+        for (name, track) in &mut tracks {
+            track.sort_by(|a, b| a.time.total_cmp(&b.time));
+            if track.windows(2).any(|pair| pair[0].time == pair[1].time) {
+                return Err(format!("several bodies named {name} were recorded"));
+            }
+        }
+        let times = || tracks.values().flatten().map(|s| s.time);
+        let start = times().fold(f64::INFINITY, f64::min);
+        let end = times().fold(f64::NEG_INFINITY, f64::max);
+        if start > end {
+            return Ok(Trajectory::default());
+        }
+        Ok(Trajectory { tracks, start, end })
+    }
+
+    // Writes the recorded history in the format `parse` reads. Tracks are keyed by name, so
+    // every body has to have its own.
+    pub fn to_csv(history: &History) -> Result<String, String> {
+        let mut csv = format!("{HEADER}\n");
+        for snapshot in &history.snapshots {
+            if let Some(name) = duplicate_name(snapshot.bodies.iter().map(|(_, _, body, _)| body.name.as_str())) {
+                return Err(format!("several bodies are named {name}"));
+            }
+            for (_, trans, body, _) in &snapshot.bodies {
+                let (p, q) = (trans.translation, trans.rotation);
+                csv += &format!(
+                    "\"{}\",{},{},{},{},{},{},{},{}\n",
+                    body.name.replace('"', "\"\""), snapshot.clock.time, p.x, p.y, p.z, q.x, q.y, q.z, q.w,
+                );
+            }
+        }
+        Ok(csv)
+    }
+
+    // Position, rotation and velocity of `name` at `time`, interpolated between samples
+    fn sample(&self, name: &str, time: f64) -> Option<(Vec3, Quat, Vec3)> {
+        let track = self.tracks.get(name)?;
+        let next = track.partition_point(|s| s.time <= time).clamp(1, track.len());
+        let a = track.get(next - 1)?;
+        let Some(b) = track.get(next) else { return Some((a.position, a.rotation, Vec3::ZERO)) };
+        let span = (b.time - a.time) as f32;
+        let s = ((time - a.time) as f32 / span).clamp(0., 1.);
+        Some((a.position.lerp(b.position, s), a.rotation.slerp(b.rotation, s), (b.position - a.position) / span))
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Playback {
+    pub trajectory: Option<Trajectory>,
+    pub time: f64,
+    // Live state when playback started, put back when it ends
+    pub live: Option<Snapshot>,
+}
+
+pub fn playing(playback: Res<Playback>) -> bool {
+    playback.trajectory.is_some()
+}
+
+// Entering loads the trajectory file; leaving returns to the live state playback started from
+#[derive(SystemParam)]
+struct PlaybackToggle<'w, 's> {
+    playback: ResMut<'w, Playback>,
+    history: ResMut<'w, History>,
+    clock: ResMut<'w, SimClock>,
+    spawner: BodySpawner<'w, 's>,
+    bodies: HistoryBodies<'w, 's>,
+}
+
+fn toggle_playback(mut toggle: PlaybackToggle) {
+    if toggle.playback.trajectory.take().is_some() {
+        if let Some(live) = toggle.playback.live.take() {
+            restore_snapshot(live, &mut toggle.history, &mut toggle.clock, &mut toggle.spawner, &mut toggle.bodies);
+        }
+        info!("Playback off");
+        return;
+    }
+    let trajectory = match fs::read_to_string(FILE).map(|text| Trajectory::parse(&text)) {
+        Ok(Ok(trajectory)) => trajectory,
+        Ok(Err(e)) => {
+            warn!("Can't play back {FILE}: {e}");
+            return;
+        },
+        Err(e) => {
+            warn!("Failed to read {FILE}: {e}");
+            return;
+        },
+    };
+    if trajectory.tracks.is_empty() {
+        warn!("No samples in {FILE}");
+        return;
+    }
+    let live = toggle.bodies.bodies.iter().map(|(_, _, body)| body.name.as_str());
+    if let Some(name) = duplicate_name(live.filter(|name| trajectory.tracks.contains_key(*name))) {
+        warn!("Can't play back {FILE}: several bodies are named {name}");
+        return;
+    }
+    for name in trajectory.tracks.keys() {
+        if !toggle.bodies.bodies.iter().any(|(_, _, body)| body.name == *name) {
+            warn!("No body named {name} to play back");
+        }
+    }
+    info!("Playing back {FILE}, t = {:.2}..{:.2}", trajectory.start, trajectory.end);
+    toggle.playback.time = trajectory.start;
+    toggle.playback.trajectory = Some(trajectory);
+    toggle.playback.live = Some(toggle.bodies.snapshot(*toggle.clock));
+}
+
+fn stop_playback(mut playback: ResMut<Playback>) {
+    playback.trajectory = None;
+    playback.live = None;
+}
+
+fn record_trajectory(history: Res<History>) {
+    let csv = match Trajectory::to_csv(&history) {
+        Ok(csv) => csv,
+        Err(e) => {
+            warn!("Not recording {FILE}: {e}");
+            return;
+        },
+    };
+    match fs::write(FILE, csv) {
+        Ok(()) => info!("Wrote {} snapshots to {FILE}", history.snapshots.len()),
+        Err(e) => warn!("Failed to write {FILE}: {e}"),
+    }
+}
+
+fn seek(direction: f64) -> impl FnMut(ResMut<Playback>) {
+    move |mut playback: ResMut<Playback>| {
+        let Some(trajectory) = &playback.trajectory else { return };
+        let (start, end) = (trajectory.start, trajectory.end);
+        playback.time = (playback.time + direction * SEEK_STEP * (end - start)).clamp(start, end);
+    }
+}
+
+// Playback follows virtual time, so pausing and warping control it like the live simulation.
+// Ring particles aren't recorded and ride along with their host.
+fn play(
+    mut playback: ResMut<Playback>,
+    mut clock: ResMut<SimClock>,
+    time: Res<Time<Virtual>>,
+    mut bodies: Query<(Entity, &mut Transform, &mut Body), Without<RingParticle>>,
+    mut particles: Query<(&RingParticle, &mut Transform)>,
+) {
+    let Playback { trajectory: Some(trajectory), time: now, .. } = &mut *playback else { return };
+    *now = (*now + time.delta_seconds_f64()).min(trajectory.end);
+    let mut moved = HashMap::new();
+    for (e, mut trans, mut body) in &mut bodies {
+        if let Some((position, rotation, vel)) = trajectory.sample(&body.name, *now) {
+            moved.insert(e, position - trans.translation);
+            trans.translation = position;
+            trans.rotation = rotation;
+            body.vel = vel;
+        }
+    }
+    for (particle, mut trans) in &mut particles {
+        if let Some(offset) = moved.get(&particle.host) {
+            trans.translation += *offset;
+        }
+    }
+    clock.time = *now;
+}
+
+#[derive(Component)]
+pub struct PlaybackPlugin;
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playback>()
+            .configure_sets(FixedUpdate, Physics.run_if(not(playing)))
+            .add_systems(Update, (
+                stop_playback.run_if(resource_changed::<Scenario>()),
+                toggle_playback.run_if(action_just_pressed(Action::TogglePlayback)),
+                record_trajectory.run_if(action_just_pressed(Action::RecordTrajectory).and_then(not(playing))),
+                seek(-1.).run_if(action_just_pressed(Action::SeekBack)),
+                seek(1.).run_if(action_just_pressed(Action::SeekForward)),
+                play,
+            ).chain().in_set(Respawn));
+    }
+}
+
+#[cfg(test)]
+mod playback_tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_samples() {
+        let text = format!("{HEADER}\n\"A\",0,0,0,0,0,0,0,1\n\"A\",2,4,0,0,0,0,0,1\nbad line\n");
+        let trajectory = Trajectory::parse(&text).unwrap();
+        assert_eq!((trajectory.start, trajectory.end), (0., 2.));
+        let (position, _, vel) = trajectory.sample("A", 0.5).unwrap();
+        assert_eq!(position, Vec3::new(1., 0., 0.));
+        assert_eq!(vel, Vec3::new(2., 0., 0.));
+        assert_eq!(trajectory.sample("A", 5.).unwrap().0, Vec3::new(4., 0., 0.));
+        assert!(trajectory.sample("B", 0.).is_none());
+    }
+
+    #[test]
+    fn names_are_quoted_and_unique() {
+        let body = |name: &str| Body {
+            name: name.to_owned(), mass: 1., radius: 1., vel: Vec3::ZERO, angular_vel: Vec3::ZERO, inertia: None,
+        };
+        let mut history = History::default();
+        let snapshot = |names: &[&str]| crate::body::Snapshot {
+            clock: SimClock::default(),
+            bodies: names.iter().map(|name| (Entity::PLACEHOLDER, Transform::IDENTITY, body(name), default())).collect(),
+        };
+        history.snapshots.push_back(snapshot(&["Ship, \"Mk 2\"", "Plain"]));
+        let trajectory = Trajectory::parse(&Trajectory::to_csv(&history).unwrap()).unwrap();
+        let mut names: Vec<_> = trajectory.tracks.keys().cloned().collect();
+        names.sort();
+        assert_eq!(names, vec!["Plain", "Ship, \"Mk 2\""]);
+
+        history.snapshots.push_back(snapshot(&["Unnamed", "Unnamed"]));
+        assert!(Trajectory::to_csv(&history).is_err());
+        let merged = format!("{HEADER}\nA,0,0,0,0,0,0,0,1\nA,0,1,0,0,0,0,0,1\n");
+        assert!(Trajectory::parse(&merged).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use bevy::{prelude::*, ecs::query::Has};
+use toml::Value;
+use std::{collections::VecDeque, fs, io::Write};
+use crate::{
+    body::{Body, Physics, Scenario, SimClock, TestParticle, angular_momentum, total_energy},
+    editor::Selection,
+    input::{Action, action_just_pressed},
+    ring::RingParticle,
+};
+
+const EXPORT_FILE: &str = "plots.csv";
+// Simulated time between samples, and how many are kept per series
+const SAMPLE_INTERVAL: f64 = 0.05;
+const MAX_SAMPLES: usize = 4000;
+// Points drawn per plot, spread over the kept samples
+const PLOT_POINTS: usize = 160;
+const PANEL_COLOR: Color = Color::rgba(0.08, 0.08, 0.1, 0.85);
+const AREA_COLOR: Color = Color::rgba(0., 0., 0., 0.5);
+const COLORS: [Color; 4] = [Color::ORANGE, Color::CYAN, Color::LIME_GREEN, Color::PINK];
+const FONT_SIZE: f32 = 14.;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Quantity {
+    Energy,
+    AngularMomentum,
+    Distance(String, String),
+    Speed(String),
+}
+impl Quantity {
+    fn label(&self) -> String {
+        match self {
+            Quantity::Energy => String::from("total energy"),
+            Quantity::AngularMomentum => String::from("|angular momentum|"),
+            Quantity::Distance(a, b) => format!("distance {a}-{b}"),
+            Quantity::Speed(a) => format!("speed of {a}"),
+        }
+    }
+    fn parse(cfg: &Value) -> Option<Quantity> {
+        let name = |key| Some(cfg.get(key)?.as_str()?.to_owned());
+        match cfg.get("quantity")?.as_str()? {
+            "energy" => Some(Quantity::Energy),
+            "angular_momentum" => Some(Quantity::AngularMomentum),
+            "distance" => Some(Quantity::Distance(name("a")?, name("b")?)),
+            "speed" => Some(Quantity::Speed(name("body")?)),
+            _ => None,
+        }
+    }
+    // Conserved quantities only count `massive` bodies, leaving out test particles
+    fn sample(&self, bodies: &[(Vec3, &Body)], massive: &[(Vec3, &Body)]) -> Option<f64> {
+        let find = |name: &str| bodies.iter().find(|(_, body)| body.name == name);
+        match self {
+            Quantity::Energy => Some(total_energy(massive)),
+            Quantity::AngularMomentum => Some(angular_momentum(massive).length() as f64),
+            Quantity::Distance(a, b) => Some(find(a)?.0.distance(find(b)?.0) as f64),
+            Quantity::Speed(a) => Some(find(a)?.1.vel.length() as f64),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Series {
+    pub quantity: Quantity,
+    // (time, value) pairs, with NaN where the quantity couldn't be evaluated
+    pub samples: VecDeque<(f64, f64)>,
+}
+impl Series {
+    // Lowest and highest finite value and the first and last sample time, which both axes
+    // scale to
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        let finite = || self.samples.iter().map(|(_, v)| *v).filter(|v| v.is_finite());
+        let lo = finite().fold(f64::INFINITY, f64::min);
+        let hi = finite().fold(f64::NEG_INFINITY, f64::max);
+        let t0 = self.samples.front().map_or(0., |s| s.0);
+        let t1 = self.samples.back().map_or(0., |s| s.0);
+        (lo, hi, t0, t1)
+    }
+
+    // Position of drawn point `j` within the plot area as fractions from the left and bottom,
+    // if its sample has a value
+    fn point(&self, j: usize, (lo, hi, t0, t1): (f64, f64, f64, f64)) -> Option<(f64, f64)> {
+        let n = self.samples.len();
+        let (t, v) = (n > 0).then(|| self.samples[j * (n - 1) / (PLOT_POINTS - 1)])?;
+        if !v.is_finite() {
+            return None;
+        }
+        let x = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1. };
+        let y = if hi > lo { (v - lo) / (hi - lo) } else { 0.5 };
+        Some((x, y))
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Plots {
+    pub series: Vec<Series>,
+    show: bool,
+}
+impl Plots {
+    // Plots listed as [[plot]] entries of the scenario, or energy and angular momentum
+    fn load(path: &str) -> Plots {
+        let config = fs::read_to_string(path).ok().and_then(|text| toml::from_str::<Value>(&text).ok());
+        let entries = config.as_ref().and_then(|c| c.get("plot")?.as_array());
+        let quantities = match entries {
+            Some(entries) => entries.iter().filter_map(|cfg| {
+                let quantity = Quantity::parse(cfg);
+                if quantity.is_none() {
+                    warn!("Skipping malformed plot {cfg}");
+                }
+                quantity
+            }).collect(),
+            None => vec![Quantity::Energy, Quantity::AngularMomentum],
+        };
+        Plots {
+            series: quantities.into_iter().map(|quantity| Series { quantity, samples: VecDeque::new() }).collect(),
+            show: false,
+        }
+    }
+    fn add(&mut self, quantity: Quantity) {
+        if !self.series.iter().any(|s| s.quantity == quantity) {
+            self.series.push(Series { quantity, samples: VecDeque::new() });
+        }
+    }
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("series,time,value\n");
+        for series in &self.series {
+            let label = series.quantity.label().replace('"', "\"\"");
+            for (t, v) in &series.samples {
+                csv += &format!("\"{label}\",{t},{v}\n");
+            }
+        }
+        csv
+    }
+}
+
+fn shown(plots: Res<Plots>) -> bool {
+    plots.show
+}
+
+// Samples only while the panel is up, as the conserved quantities sum over every pair of
+// bodies. Ring particles are left out altogether and test particles from those sums.
+fn sample_plots(
+    mut plots: ResMut<Plots>,
+    clock: Res<SimClock>,
+    bodies: Query<(&Transform, &Body, Has<TestParticle>), Without<RingParticle>>,
+) {
+    let state: Vec<_> = bodies.iter().map(|(trans, body, _)| (trans.translation, body)).collect();
+    let massive: Vec<_> = bodies.iter().filter(|b| !b.2).map(|(trans, body, _)| (trans.translation, body)).collect();
+    for series in &mut plots.series {
+        // Rewinding the clock restarts the series from the restored instant
+        while series.samples.back().is_some_and(|(t, _)| *t > clock.time) {
+            series.samples.pop_back();
+        }
+        if series.samples.back().is_some_and(|(t, _)| clock.time - t < SAMPLE_INTERVAL) {
+            continue;
+        }
+        series.samples.push_back((clock.time, series.quantity.sample(&state, &massive).unwrap_or(f64::NAN)));
+        if series.samples.len() > MAX_SAMPLES {
+            series.samples.pop_front();
+        }
+    }
+}
+
+fn reload_plots(mut plots: ResMut<Plots>, scenario: Res<Scenario>) {
+    let show = plots.show;
+    *plots = Plots { show, ..Plots::load(&scenario.0) };
+}
+
+fn toggle_plots(mut plots: ResMut<Plots>) {
+    plots.show = !plots.show;
+}
+
+// Adds the selected body's speed, or its distance to the body whose speed was plotted last
+fn plot_selected(mut plots: ResMut<Plots>, selection: Res<Selection>, bodies: Query<&Body>) {
+    let Some(body) = selection.0.and_then(|e| bodies.get(e).ok()) else { return };
+    let last_speed = plots.series.iter().rev().find_map(|s| match &s.quantity {
+        Quantity::Speed(name) => Some(name.clone()),
+        _ => None,
+    });
+    match last_speed {
+        Some(other) if other != body.name => plots.add(Quantity::Distance(other, body.name.clone())),
+        _ => plots.add(Quantity::Speed(body.name.clone())),
+    }
+    plots.show = true;
+}
+
+fn export_plots(plots: Res<Plots>) {
+    match fs::File::create(EXPORT_FILE).and_then(|mut f| f.write_all(plots.to_csv().as_bytes())) {
+        Ok(()) => info!("Exported plots to {EXPORT_FILE}"),
+        Err(e) => warn!("Failed to write {EXPORT_FILE}: {e}"),
+    }
+}
+
+#[derive(Component)]
+struct PlotPanel;
+#[derive(Component)]
+struct PlotTitle(usize);
+#[derive(Component)]
+struct PlotPoint(usize, usize);
+
+fn build_panel(
+    mut commands: Commands,
+    plots: Res<Plots>,
+    mut shown: Local<Option<usize>>,
+    panel: Query<Entity, With<PlotPanel>>,
+) {
+    let count = if plots.show { plots.series.len() } else { 0 };
+    if *shown == Some(count) {
+        return;
+    }
+    *shown = Some(count);
+    for e in &panel {
+        commands.entity(e).despawn_recursive();
+    }
+    if count == 0 {
+        return;
+    }
+    let text_style = TextStyle { font_size: FONT_SIZE, color: Color::WHITE, ..default() };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.),
+                top: Val::Px(8.),
+                width: Val::Px(340.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.),
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        PlotPanel,
+    )).with_children(|panel| {
+        for i in 0..count {
+            panel.spawn((TextBundle::from_section("", text_style.clone()), PlotTitle(i)));
+            panel.spawn(NodeBundle {
+                style: Style { width: Val::Percent(100.), height: Val::Px(70.), ..default() },
+                background_color: AREA_COLOR.into(),
+                ..default()
+            }).with_children(|area| {
+                for j in 0..PLOT_POINTS {
+                    area.spawn((
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                width: Val::Px(2.),
+                                height: Val::Px(2.),
+                                ..default()
+                            },
+                            background_color: COLORS[i % COLORS.len()].into(),
+                            visibility: Visibility::Hidden,
+                            ..default()
+                        },
+                        PlotPoint(i, j),
+                    ));
+                }
+            });
+        }
+    });
+}
+
+// Both axes scale to the kept samples of each series
+fn draw_plots(
+    plots: Res<Plots>,
+    mut titles: Query<(&mut Text, &PlotTitle)>,
+    mut points: Query<(&mut Style, &mut Visibility, &PlotPoint)>,
+) {
+    if !plots.show {
+        return;
+    }
+    let ranges: Vec<_> = plots.series.iter().map(Series::bounds).collect();
+    for (mut text, PlotTitle(i)) in &mut titles {
+        let Some(series) = plots.series.get(*i) else { continue };
+        let (lo, hi, ..) = ranges[*i];
+        let current = series.samples.back().map_or(f64::NAN, |s| s.1);
+        text.sections[0].value = format!("{}  {current:.6e}\n[{lo:.4e}, {hi:.4e}]", series.quantity.label());
+    }
+    for (mut style, mut vis, PlotPoint(i, j)) in &mut points {
+        let Some(series) = plots.series.get(*i) else { continue };
+        match series.point(*j, ranges[*i]) {
+            Some((x, y)) => {
+                style.left = Val::Percent(100. * x as f32);
+                style.top = Val::Percent(100. * (1. - y) as f32);
+                *vis = Visibility::Inherited;
+            },
+            None => *vis = Visibility::Hidden,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PlotPlugin;
+impl Plugin for PlotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Plots>()
+            .add_systems(FixedUpdate, sample_plots.after(Physics).run_if(shown))
+            .add_systems(Update, (
+                reload_plots.run_if(resource_changed::<Scenario>()),
+                toggle_plots.run_if(action_just_pressed(Action::TogglePlots)),
+                plot_selected.run_if(action_just_pressed(Action::PlotSelected)),
+                export_plots.run_if(action_just_pressed(Action::ExportPlots)),
+                build_panel,
+                draw_plots,
+            ).chain());
+    }
+}
+
+#[cfg(test)]
+mod plot_tests {
+    use super::*;
+
+    #[test]
+    fn parse_quantities() {
+        let cfg: Value = toml::from_str("quantity = \"distance\"\na = \"A\"\nb = \"B\"").unwrap();
+        assert_eq!(Quantity::parse(&cfg), Some(Quantity::Distance("A".into(), "B".into())));
+        let cfg: Value = toml::from_str("quantity = \"speed\"").unwrap();
+        assert_eq!(Quantity::parse(&cfg), None);
+    }
+
+    #[test]
+    fn axes_scale_to_samples() {
+        let series = Series {
+            quantity: Quantity::Energy,
+            samples: [(1., -2.), (2., f64::NAN), (3., 6.), (5., 2.)].into_iter().collect(),
+        };
+        let bounds = series.bounds();
+        assert_eq!(bounds, (-2., 6., 1., 5.));
+        assert_eq!(series.point(0, bounds), Some((0., 0.)));
+        assert_eq!(series.point(PLOT_POINTS - 1, bounds), Some((1., 0.5)));
+        // Points spread over the samples, skipping the missing value
+        let points: Vec<_> = (0..PLOT_POINTS).filter_map(|j| series.point(j, bounds)).collect();
+        assert!(points.contains(&(0.5, 1.)));
+        assert!(points.len() < PLOT_POINTS);
+        // A flat series sits in the middle
+        let flat = Series { quantity: Quantity::Energy, samples: [(0., 3.)].into_iter().collect() };
+        assert_eq!(flat.point(7, flat.bounds()), Some((1., 0.5)));
+    }
+
+    #[test]
+    fn csv_quotes_labels() {
+        let plots = Plots {
+            series: vec![
+                Series { quantity: Quantity::Speed("Ship \"Mk 2\"".into()), samples: [(0., 1.5), (0.5, 2.)].into_iter().collect() },
+                Series { quantity: Quantity::Distance("A".into(), "B, C".into()), samples: [(0., 4.)].into_iter().collect() },
+            ],
+            show: false,
+        };
+        assert_eq!(
+            plots.to_csv(),
+            "series,time,value\n\"speed of Ship \"\"Mk 2\"\"\",0,1.5\n\"speed of Ship \"\"Mk 2\"\"\",0.5,2\n\"distance A-B, C\",0,4\n",
+        );
+    }
+}
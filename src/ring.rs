@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use toml::Value;
+use std::f32::consts::TAU;
+use crate::body::G;
+
+const DEFAULT_PARTICLE_RADIUS: f32 = 0.005;
+const PARTICLE_COLOR: Color = Color::rgb(0.8, 0.75, 0.65);
+
+// Member of a ring or debris disk around `host`. There can be thousands, so they are left out of
+// body lists, labels and the rewind history, which regenerates the rings of restored hosts.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct RingParticle {
+    pub host: Entity,
+}
+
+// Massless particles on circular orbits between `inner` and `outer` radius of a body, in the
+// plane facing `normal`. Kept on the host so its ring can be regenerated.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Ring {
+    pub inner: f32,
+    pub outer: f32,
+    pub count: usize,
+    pub normal: Vec3,
+    pub seed: u64,
+    pub particle_radius: f32,
+}
+
+// SplitMix64, enough for scattering particles reproducibly
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as f32 / u64::MAX as f32
+    }
+}
+
+impl Ring {
+    // Reads the `ring` table of a scenario body
+    pub fn parse(cfg: &Value) -> Option<Ring> {
+        let get_f = |cfg: &Value, key| Some(cfg.get(key)?.as_float()? as f32);
+        let normal = match cfg.get("normal") {
+            Some(n) => Vec3::new(get_f(n, "x")?, get_f(n, "y")?, get_f(n, "z")?).try_normalize()?,
+            None => Vec3::Z,
+        };
+        let ring = Ring {
+            inner: get_f(cfg, "inner")?,
+            outer: get_f(cfg, "outer")?,
+            count: usize::try_from(cfg.get("count")?.as_integer()?).ok()?,
+            normal,
+            seed: cfg.get("seed").and_then(Value::as_integer).unwrap_or(0) as u64,
+            particle_radius: get_f(cfg, "particle_radius").unwrap_or(DEFAULT_PARTICLE_RADIUS),
+        };
+        (0. < ring.inner && ring.inner <= ring.outer).then_some(ring)
+    }
+
+    // Positions and velocities of the particles around a body of `mass` at `center` moving at `vel`
+    pub fn particles(&self, mass: f32, center: Vec3, vel: Vec3) -> Vec<(Vec3, Vec3)> {
+        let mut rng = Rng(self.seed);
+        let (u, v) = self.normal.any_orthonormal_pair();
+        (0..self.count).map(|_| {
+            // Uniform over the annulus' area
+            let r = (self.inner.powi(2) + rng.next() * (self.outer.powi(2) - self.inner.powi(2))).sqrt();
+            let angle = TAU * rng.next();
+            let radial = angle.cos() * u + angle.sin() * v;
+            let speed = (G * mass / r).sqrt();
+            (center + r * radial, vel + speed * self.normal.cross(radial))
+        }).collect()
+    }
+
+    pub fn mesh(&self) -> Mesh {
+        shape::UVSphere { radius: self.particle_radius, sectors: 8, stacks: 4 }.into()
+    }
+
+    pub fn material() -> StandardMaterial {
+        StandardMaterial { base_color: PARTICLE_COLOR, perceptual_roughness: 1., ..default() }
+    }
+}
+
+#[cfg(test)]
+mod ring_tests {
+    use super::*;
+
+    #[test]
+    fn circular_orbits_in_plane() {
+        let cfg: Value = toml::from_str("inner = 1.0\nouter = 2.0\ncount = 500\nseed = 7\nnormal = { x = 0.0, y = 1.0, z = 1.0 }").unwrap();
+        let ring = Ring::parse(&cfg).unwrap();
+        let (mass, center, vel) = (1. / G, Vec3::new(3., 0., 0.), Vec3::X);
+        let particles = ring.particles(mass, center, vel);
+        assert_eq!(particles.len(), 500);
+        assert_eq!(particles, ring.particles(mass, center, vel));
+        for (pos, v) in particles {
+            let (r, v) = (pos - center, v - vel);
+            assert!((1. ..=2.).contains(&r.length()));
+            assert!(r.dot(ring.normal).abs() < 1e-5 && v.dot(ring.normal).abs() < 1e-5);
+            // Circular: v² = GM/r
+            assert!((v.length_squared() * r.length() - 1.).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use bevy::{prelude::*, ecs::query::Has};
+use toml::Value;
+use std::{collections::HashSet, f32::consts::PI, fs};
+use crate::{
+    body::{G, Body, BodySpawner, Physics, Scenario, TestParticle, update_bodies},
+    ring::RingParticle,
+};
+
+// Fragments per disrupted body and their velocity spread as a fraction of the victim's surface
+// escape speed, unless the scenario's [roche] table says otherwise
+const DEFAULT_FRAGMENTS: usize = 8;
+const DEFAULT_DISPERSION: f32 = 0.5;
+
+// Tidal disruption rule, on when the scenario has a [roche] table
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Roche(pub Option<RocheRule>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RocheRule {
+    pub fragments: usize,
+    pub dispersion: f32,
+}
+
+// Sent when a body breaks up inside another's Roche limit
+#[derive(Event, Clone, Debug)]
+pub struct Disrupted {
+    pub victim: String,
+    pub primary: Entity,
+    pub fragments: Vec<Entity>,
+}
+
+// Pieces of a disrupted body. They share its density, and so its Roche limit, so they are
+// not broken up again. The history and duplicates keep the marker.
+#[derive(Component)]
+pub struct Fragment;
+
+fn density(body: &Body) -> f32 {
+    body.mass / (4. / 3. * PI * body.radius.powi(3))
+}
+
+// Distance from `primary` inside which a rigid `victim` is pulled apart
+pub fn roche_limit(primary: &Body, victim: &Body) -> f32 {
+    primary.radius * (2. * density(primary) / density(victim)).cbrt()
+}
+
+// Splits `victim` at `position` into `n` equal pieces of the same density, spread through its
+// volume and flying apart at `spread`, keeping the centre of mass and momentum unchanged
+pub fn fragment(victim: &Body, position: Vec3, n: usize, spread: f32) -> Vec<(Body, Vec3)> {
+    // Evenly spaced directions on a sphere
+    let dirs: Vec<Vec3> = (0..n).map(|k| {
+        let z = 1. - (2 * k + 1) as f32 / n as f32;
+        let angle = PI * (3. - 5_f32.sqrt()) * k as f32;
+        let rho = (1. - z * z).sqrt();
+        Vec3::new(rho * angle.cos(), rho * angle.sin(), z)
+    }).collect();
+    let mean = dirs.iter().sum::<Vec3>() / n as f32;
+    dirs.iter().enumerate().map(|(k, dir)| {
+        let dir = *dir - mean;
+        (
+            Body {
+                name: format!("{} fragment {}", victim.name, k + 1),
+                mass: victim.mass / n as f32,
+                radius: victim.radius / (n as f32).cbrt(),
+                vel: victim.vel + spread * dir,
+                angular_vel: victim.angular_vel,
+                inertia: None,
+            },
+            position + 0.5 * victim.radius * dir,
+        )
+    }).collect()
+}
+
+fn load_roche(mut roche: ResMut<Roche>, scenario: Res<Scenario>) {
+    let config = fs::read_to_string(&scenario.0).ok().and_then(|text| toml::from_str::<Value>(&text).ok());
+    *roche = Roche(config.as_ref().and_then(|c| c.get("roche")).map(|cfg| RocheRule {
+        fragments: cfg.get("fragments").and_then(Value::as_integer).map_or(DEFAULT_FRAGMENTS, |n| n.max(2) as usize),
+        dispersion: cfg.get("dispersion").and_then(Value::as_float).map_or(DEFAULT_DISPERSION, |d| d as f32),
+    }));
+}
+
+// Fragments are plain rubble: the victim's ring goes with it, and so do any thrusters, stellar
+// light or extra forces it had
+fn disrupt(
+    roche: Res<Roche>,
+    mut spawner: BodySpawner,
+    mut events: EventWriter<Disrupted>,
+    bodies: Query<(Entity, &Transform, &Body, Has<Fragment>), Without<TestParticle>>,
+    particles: Query<(Entity, &RingParticle)>,
+) {
+    let Some(rule) = roche.0 else { return };
+    let mut broken = HashSet::new();
+    for (victim_e, victim_trans, victim, is_fragment) in &bodies {
+        if is_fragment {
+            continue;
+        }
+        let primary = bodies.iter().find(|(e, trans, primary, _)| {
+            *e != victim_e && !broken.contains(e) && primary.mass > victim.mass
+                && trans.translation.distance(victim_trans.translation) < roche_limit(primary, victim)
+        });
+        let Some((primary_e, ..)) = primary else { continue };
+        broken.insert(victim_e);
+        let escape = (2. * G * victim.mass / victim.radius).sqrt();
+        let fragments = fragment(victim, victim_trans.translation, rule.fragments, rule.dispersion * escape)
+            .into_iter()
+            .map(|(body, position)| {
+                let e = spawner.spawn(body, position);
+                spawner.commands.entity(e).insert((Fragment, victim_trans.with_translation(position)));
+                e
+            })
+            .collect();
+        spawner.commands.entity(victim_e).despawn_recursive();
+        for (particle, _) in particles.iter().filter(|(_, particle)| particle.host == victim_e) {
+            spawner.commands.entity(particle).despawn_recursive();
+        }
+        events.send(Disrupted { victim: victim.name.clone(), primary: primary_e, fragments });
+    }
+}
+
+fn announce_disruptions(mut events: EventReader<Disrupted>, bodies: Query<&Body>) {
+    for ev in events.read() {
+        let primary = bodies.get(ev.primary).map_or("?", |body| body.name.as_str());
+        info!("{} was torn into {} fragments by {primary}", ev.victim, ev.fragments.len());
+    }
+}
+
+#[derive(Component)]
+pub struct RochePlugin;
+impl Plugin for RochePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Roche>()
+            .add_event::<Disrupted>()
+            .add_systems(Update, (load_roche.run_if(resource_changed::<Scenario>()), announce_disruptions))
+            .add_systems(FixedUpdate, disrupt.in_set(Physics).after(update_bodies));
+    }
+}
+
+#[cfg(test)]
+mod roche_tests {
+    use crate::body::body_tests::{history_app, record_at, restore};
+    use super::*;
+
+    #[test]
+    fn fragments_conserve_mass_and_momentum() {
+        let victim = Body {
+            name: "Moon".to_owned(),
+            mass: 8.,
+            radius: 2.,
+            vel: Vec3::new(1., 2., 3.),
+            angular_vel: Vec3::ZERO,
+            inertia: None,
+        };
+        let position = Vec3::new(5., 0., -1.);
+        let pieces = fragment(&victim, position, 7, 0.3);
+        assert_eq!(pieces.len(), 7);
+        let mass: f32 = pieces.iter().map(|(b, _)| b.mass).sum();
+        let momentum: Vec3 = pieces.iter().map(|(b, _)| b.mass * b.vel).sum();
+        let centre: Vec3 = pieces.iter().map(|(b, p)| b.mass * *p).sum::<Vec3>() / mass;
+        assert!((mass - victim.mass).abs() < 1e-5);
+        assert!((momentum - victim.mass * victim.vel).length() < 1e-4);
+        assert!((centre - position).length() < 1e-5);
+        assert!(pieces.iter().all(|(b, _)| b.vel != victim.vel && (density(b) - density(&victim)).abs() < 1e-4));
+        // Equal densities put the limit at 2^(1/3) primary radii
+        assert!((roche_limit(&victim, &pieces[0].0) - 2. * 2_f32.cbrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rewinding_keeps_fragments_whole() {
+        let mut app = history_app();
+        app.init_resource::<Roche>()
+            .add_event::<Disrupted>()
+            .add_systems(Update, (disrupt, apply_deferred).chain().before(Physics));
+        let body = |name: &str, mass, radius| Body {
+            name: name.to_owned(), mass, radius, vel: Vec3::ZERO, angular_vel: Vec3::ZERO, inertia: None,
+        };
+        app.world.spawn((Transform::IDENTITY, body("Planet", 1000., 1.)));
+        app.world.spawn((Transform::from_xyz(3., 0., 0.), body("Moon", 1., 0.5)));
+        let count = |app: &mut App| app.world.query::<&Body>().iter(&app.world).count();
+
+        record_at(&mut app, 0., &[]);
+        *app.world.resource_mut::<Roche>() = Roche(Some(RocheRule { fragments: 8, dispersion: 0.5 }));
+        record_at(&mut app, 0.25, &[]);
+        assert_eq!(count(&mut app), 9);
+        // Back before the break-up, then forward onto the respawned fragments
+        restore(&mut app, 0);
+        assert_eq!(count(&mut app), 2);
+        restore(&mut app, 1);
+        assert_eq!(count(&mut app), 9);
+        record_at(&mut app, 0.5, &[]);
+        assert_eq!(count(&mut app), 9);
+        assert_eq!(app.world.query_filtered::<(), With<Fragment>>().iter(&app.world).count(), 8);
+    }
+}
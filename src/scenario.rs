@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use toml::Value;
+use std::fs;
+use crate::{body::{SCENARIO_DIR, Scenario}, input::{Action, action_just_pressed}};
+
+const PANEL_COLOR: Color = Color::rgba(0.08, 0.08, 0.1, 0.9);
+const IDLE_COLOR: Color = Color::rgb(0.18, 0.18, 0.22);
+const HOVER_COLOR: Color = Color::rgb(0.26, 0.26, 0.32);
+const ACTIVE_COLOR: Color = Color::rgb(0.2, 0.35, 0.6);
+const TITLE_SIZE: f32 = 18.;
+const DESCRIPTION_SIZE: f32 = 14.;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioInfo {
+    pub path: String,
+    pub title: String,
+    pub description: String,
+}
+
+// Title and description of every scenario in `dir`, skipping sidecar files
+pub fn list_scenarios(dir: &str) -> Vec<ScenarioInfo> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        warn!("Failed to read scenario directory {dir}");
+        return Vec::new();
+    };
+    let mut scenarios: Vec<_> = entries.filter_map(|entry| {
+        let path = entry.ok()?.path();
+        let stem = path.file_stem()?.to_str()?;
+        if path.extension()? != "toml" || stem.contains('.') {
+            return None;
+        }
+        let config: Value = toml::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        let text = |key| config.get(key).and_then(Value::as_str).map(str::to_owned);
+        Some(ScenarioInfo {
+            title: text("title").unwrap_or_else(|| stem.to_owned()),
+            description: text("description").unwrap_or_default(),
+            path: path.to_str()?.to_owned(),
+        })
+    }).collect();
+    scenarios.sort_by(|a, b| a.title.cmp(&b.title));
+    scenarios
+}
+
+#[derive(Component)]
+struct ScenarioMenu;
+#[derive(Component)]
+struct ScenarioButton(String);
+
+fn setup_menu(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(30.),
+                right: Val::Percent(30.),
+                top: Val::Px(40.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.),
+                padding: UiRect::all(Val::Px(10.)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        ScenarioMenu,
+    ));
+}
+
+// Rescans the directory on opening, so scenarios added while running show up
+fn toggle_menu(mut commands: Commands, mut menu: Query<(Entity, &mut Visibility), With<ScenarioMenu>>) {
+    let (menu, mut vis) = menu.single_mut();
+    if *vis != Visibility::Hidden {
+        *vis = Visibility::Hidden;
+        return;
+    }
+    *vis = Visibility::Inherited;
+    commands.entity(menu).despawn_descendants().with_children(|menu| {
+        menu.spawn(TextBundle::from_section(
+            format!("Scenarios in {SCENARIO_DIR}"),
+            TextStyle { font_size: TITLE_SIZE, color: Color::WHITE, ..default() },
+        ));
+        for info in list_scenarios(SCENARIO_DIR) {
+            menu.spawn((
+                ButtonBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(6.)),
+                        ..default()
+                    },
+                    background_color: IDLE_COLOR.into(),
+                    ..default()
+                },
+                ScenarioButton(info.path),
+            )).with_children(|button| {
+                button.spawn(TextBundle::from_section(
+                    info.title,
+                    TextStyle { font_size: TITLE_SIZE, color: Color::WHITE, ..default() },
+                ));
+                button.spawn(TextBundle::from_section(
+                    info.description,
+                    TextStyle { font_size: DESCRIPTION_SIZE, color: Color::GRAY, ..default() },
+                ));
+            });
+        }
+    });
+}
+
+fn choose_scenario(
+    mut scenario: ResMut<Scenario>,
+    mut menu: Query<&mut Visibility, With<ScenarioMenu>>,
+    mut buttons: Query<(&Interaction, &mut BackgroundColor, &ScenarioButton)>,
+) {
+    for (interaction, mut color, ScenarioButton(path)) in &mut buttons {
+        *color = match interaction {
+            _ if *path == scenario.0 => ACTIVE_COLOR,
+            Interaction::Hovered => HOVER_COLOR,
+            Interaction::Pressed => {
+                scenario.0 = path.clone();
+                *menu.single_mut() = Visibility::Hidden;
+                ACTIVE_COLOR
+            },
+            Interaction::None => IDLE_COLOR,
+        }.into();
+    }
+}
+
+#[derive(Component)]
+pub struct ScenarioPlugin;
+impl Plugin for ScenarioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_menu)
+            .add_systems(Update, (
+                toggle_menu.run_if(action_just_pressed(Action::ToggleScenarios)),
+                choose_scenario,
+            ).chain());
+    }
+}
+
+#[cfg(test)]
+mod scenario_tests {
+    use super::*;
+
+    #[test]
+    fn bundled_scenarios_have_bodies() {
+        let scenarios = list_scenarios(SCENARIO_DIR);
+        assert!(scenarios.len() >= 5);
+        for info in scenarios {
+            assert!(!info.description.is_empty(), "{} has no description", info.path);
+            let config: Value = toml::from_str(&fs::read_to_string(&info.path).unwrap()).unwrap();
+            assert!(config["body"].as_array().is_some_and(|bodies| !bodies.is_empty()), "{} has no bodies", info.path);
+        }
+    }
+}
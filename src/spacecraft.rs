@@ -0,0 +1,180 @@
+use bevy::prelude::*;
+use toml::Value;
+use crate::{
+    body::{Body, Physics, SimClock, update_bodies},
+    editor::Selection,
+    input::{Action, Actions},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BurnDirection {
+    Prograde,
+    Retrograde,
+    // World-frame direction
+    Fixed(Vec3),
+}
+impl BurnDirection {
+    fn resolve(self, vel: Vec3) -> Option<Vec3> {
+        match self {
+            BurnDirection::Prograde => vel.try_normalize(),
+            BurnDirection::Retrograde => (-vel).try_normalize(),
+            BurnDirection::Fixed(dir) => dir.try_normalize(),
+        }
+    }
+}
+
+// Engine firing at full thrust from `start` for `duration` seconds of simulated time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burn {
+    pub start: f64,
+    pub duration: f64,
+    pub direction: BurnDirection,
+}
+
+// Rocket whose `Body.mass` is its dry mass plus remaining propellant. Edits to the body's mass,
+// including rewinds, count as refuelling or draining the tanks.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Spacecraft {
+    pub dry_mass: f32,
+    pub propellant: f32,
+    pub exhaust_velocity: f32,
+    // Force at full throttle
+    pub thrust: f32,
+    pub burns: Vec<Burn>,
+    // Burn requested from the controls this frame
+    pub manual: Option<BurnDirection>,
+}
+impl Spacecraft {
+    pub fn mass(&self) -> f32 {
+        self.dry_mass + self.propellant
+    }
+
+    // Reads a `spacecraft` table of a scenario body, with burns listed as
+    // { start, duration, direction } where direction is "prograde", "retrograde" or { x, y, z }
+    pub fn parse(cfg: &Value) -> Option<Spacecraft> {
+        let get_f = |cfg: &Value, key| Some(cfg.get(key)?.as_float()? as f32);
+        let parse_burn = |cfg: &Value| -> Option<Burn> {
+            let direction = match cfg.get("direction")? {
+                Value::String(s) if s == "prograde" => BurnDirection::Prograde,
+                Value::String(s) if s == "retrograde" => BurnDirection::Retrograde,
+                dir => BurnDirection::Fixed(Vec3::new(get_f(dir, "x")?, get_f(dir, "y")?, get_f(dir, "z")?)),
+            };
+            Some(Burn { start: cfg.get("start")?.as_float()?, duration: cfg.get("duration")?.as_float()?, direction })
+        };
+        let burns = cfg.get("burns").and_then(Value::as_array).map_or(&[][..], Vec::as_slice);
+        Some(Spacecraft {
+            dry_mass: get_f(cfg, "dry_mass")?,
+            propellant: get_f(cfg, "propellant").unwrap_or(0.),
+            exhaust_velocity: get_f(cfg, "exhaust_velocity")?,
+            thrust: get_f(cfg, "thrust")?,
+            burns: burns.iter().filter_map(|burn| {
+                let parsed = parse_burn(burn);
+                if parsed.is_none() {
+                    warn!("Skipping malformed burn {burn}");
+                }
+                parsed
+            }).collect(),
+            manual: None,
+        })
+    }
+
+    // Fires along `dir` for up to `dt`, spending propellant at thrust/exhaust velocity and
+    // gaining the rocket equation's Δv = ve·ln(m0/m1). Returns the Δv.
+    pub fn fire(&mut self, dir: Vec3, dt: f32) -> Vec3 {
+        if self.exhaust_velocity <= 0. || self.propellant <= 0. {
+            return Vec3::ZERO;
+        }
+        let spent = (self.thrust / self.exhaust_velocity * dt).min(self.propellant);
+        let before = self.mass();
+        self.propellant -= spent;
+        self.exhaust_velocity * (before / self.mass()).ln() * dir
+    }
+}
+
+// Sets the manual burn of the selected ship while a burn key is held
+fn manual_burns(actions: Actions, selection: Res<Selection>, mut ships: Query<(Entity, &mut Spacecraft)>) {
+    let direction = if actions.pressed(Action::BurnPrograde) {
+        Some(BurnDirection::Prograde)
+    } else if actions.pressed(Action::BurnRetrograde) {
+        Some(BurnDirection::Retrograde)
+    } else {
+        None
+    };
+    for (e, mut ship) in &mut ships {
+        let manual = direction.filter(|_| selection.0 == Some(e));
+        if ship.manual != manual {
+            ship.manual = manual;
+        }
+    }
+}
+
+// Runs after each gravity step, splitting the engine's push from the orbit integration
+fn fire_engines(clock: Res<SimClock>, time: Res<Time>, mut ships: Query<(&mut Spacecraft, &mut Body)>) {
+    let dt = time.delta_seconds();
+    // The clock has already moved past this step
+    let start = clock.time - dt as f64;
+    for (mut ship, mut body) in &mut ships {
+        if body.mass != ship.mass() {
+            ship.propellant = (body.mass - ship.dry_mass).max(0.);
+        }
+        let scheduled = ship.burns.iter().find_map(|burn| {
+            let overlap = (start + dt as f64).min(burn.start + burn.duration) - start.max(burn.start);
+            (overlap > 0.).then_some((burn.direction, overlap as f32))
+        });
+        let Some((direction, duration)) = ship.manual.map(|d| (d, dt)).or(scheduled) else { continue };
+        let Some(dir) = direction.resolve(body.vel) else { continue };
+        let had_propellant = ship.propellant > 0.;
+        body.vel += ship.fire(dir, duration);
+        body.mass = ship.mass();
+        if had_propellant && ship.propellant <= 0. {
+            info!("{} is out of propellant", body.name);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct SpacecraftPlugin;
+impl Plugin for SpacecraftPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, manual_burns)
+            .add_systems(FixedUpdate, fire_engines.in_set(Physics).after(update_bodies));
+    }
+}
+
+#[cfg(test)]
+mod spacecraft_tests {
+    use super::*;
+
+    #[test]
+    fn rocket_equation() {
+        let mut ship = Spacecraft {
+            dry_mass: 1000.,
+            propellant: 500.,
+            exhaust_velocity: 2.,
+            thrust: 200.,
+            burns: Vec::new(),
+            manual: None,
+        };
+        let mut dv = Vec3::ZERO;
+        // Enough steps to run the tanks dry after 5 s
+        for _ in 0..1000 {
+            dv += ship.fire(Vec3::X, 0.01);
+        }
+        assert_eq!(ship.propellant, 0.);
+        assert!((dv.x - 2. * 1.5_f32.ln()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn parse_ship() {
+        let cfg: Value = toml::from_str(r#"
+            dry_mass = 1000.0
+            propellant = 500.0
+            exhaust_velocity = 2.0
+            thrust = 200.0
+            burns = [{ start = 1.0, duration = 0.5, direction = "retrograde" }, { start = 3.0 }]
+        "#).unwrap();
+        let ship = Spacecraft::parse(&cfg).unwrap();
+        assert_eq!(ship.mass(), 1500.);
+        assert_eq!(ship.burns, vec![Burn { start: 1., duration: 0.5, direction: BurnDirection::Retrograde }]);
+    }
+}
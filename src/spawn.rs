@@ -0,0 +1,167 @@
+use bevy::{prelude::*, ecs::system::SystemParam, window::PrimaryWindow};
+use std::collections::HashSet;
+use crate::{
+    body::{Body, BodySpawner, Extras, Respawn},
+    camera::CenterCam,
+    editor::Selection,
+    frame::{ViewFrame, frame_state},
+    input::{Action, Actions, action_just_pressed},
+    ring::RingParticle,
+};
+
+// Name, mass and radius of the bodies that can be placed
+const PRESETS: [(&str, f32, f32); 3] = [
+    ("Moonlet", 1.5e9, 0.05),
+    ("Planet", 1.5e10, 0.1),
+    ("Giant", 1.5e11, 0.2),
+];
+// Launch speed per world unit of drag
+const SLINGSHOT_GAIN: f32 = 1.;
+const ARROW_COLOR: Color = Color::ORANGE;
+// Copies are placed this many radii along +X from the original
+const DUPLICATE_OFFSET: f32 = 3.;
+
+#[derive(Resource, Default)]
+struct Slingshot {
+    active: bool,
+    preset: usize,
+    // Where the body was placed, in view coordinates
+    anchor: Option<Vec3>,
+}
+
+fn toggle_slingshot(mut slingshot: ResMut<Slingshot>) {
+    slingshot.active = !slingshot.active;
+    slingshot.anchor = None;
+    info!("Body placement {}", if slingshot.active { "on" } else { "off" });
+}
+
+fn cycle_preset(mut slingshot: ResMut<Slingshot>) {
+    slingshot.preset = (slingshot.preset + 1) % PRESETS.len();
+    info!("Body preset: {}", PRESETS[slingshot.preset].0);
+}
+
+#[derive(SystemParam)]
+struct ViewCursor<'w, 's> {
+    window: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    cam: Query<'w, 's, (&'static Camera, &'static GlobalTransform, &'static Transform, &'static CenterCam)>,
+    ui: Query<'w, 's, &'static Interaction>,
+}
+impl ViewCursor<'_, '_> {
+    // Point under the cursor on the plane through the camera focus facing the camera
+    fn on_view_plane(&self) -> Option<Vec3> {
+        let cursor = self.window.get_single().ok()?.cursor_position()?;
+        let (camera, global, trans, center) = self.cam.get_single().ok()?;
+        let ray = camera.viewport_to_world(global, cursor)?;
+        let focus = trans.translation - center.offset;
+        Some(ray.get_point(ray.intersect_plane(focus, trans.forward())?))
+    }
+    fn over_ui(&self) -> bool {
+        self.ui.iter().any(|i| *i != Interaction::None)
+    }
+}
+
+fn slingshot(
+    mut slingshot: ResMut<Slingshot>,
+    mut spawner: BodySpawner,
+    mut gizmos: Gizmos,
+    actions: Actions,
+    frame: Res<ViewFrame>,
+    view_cursor: ViewCursor,
+    bodies: Query<(Entity, &Transform, &Body)>,
+) {
+    if !slingshot.active {
+        return;
+    }
+    let (name, mass, radius) = PRESETS[slingshot.preset];
+    let Some(cursor) = view_cursor.on_view_plane() else { return };
+    let Some(anchor) = slingshot.anchor else {
+        gizmos.sphere(cursor, Quat::IDENTITY, radius, ARROW_COLOR);
+        if actions.just_pressed(Action::PlaceBody) && !view_cursor.over_ui() {
+            slingshot.anchor = Some(cursor);
+        }
+        return;
+    };
+    // Pull back like a slingshot: the body launches away from the cursor
+    let pull = anchor - cursor;
+    gizmos.sphere(anchor, Quat::IDENTITY, radius, ARROW_COLOR);
+    let tip = anchor + pull;
+    gizmos.line(anchor, tip, ARROW_COLOR);
+    if let Some(back) = (anchor - tip).try_normalize() {
+        let side = 0.3 * back.any_orthonormal_vector();
+        let head = 0.1 * (tip - anchor).length();
+        gizmos.line(tip, tip + head * (back + side), ARROW_COLOR);
+        gizmos.line(tip, tip + head * (back - side), ARROW_COLOR);
+    }
+    if actions.pressed(Action::PlaceBody) {
+        return;
+    }
+    slingshot.anchor = None;
+    let Some(state) = frame_state(*frame, &bodies) else { return };
+    let (position, vel) = state.to_inertial(anchor, SLINGSHOT_GAIN * pull);
+    let name = free_name(name, bodies.iter().map(|(_, _, body)| body.name.as_str()));
+    info!("Spawned {name}");
+    spawner.spawn(Body { name, mass, radius, vel, angular_vel: Vec3::ZERO, inertia: None }, position);
+}
+
+// `prefix` with the lowest number not yet taken, as bookmarks, plots and playback find bodies
+// by name
+fn free_name<'a>(prefix: &str, names: impl Iterator<Item = &'a str>) -> String {
+    let taken: HashSet<&str> = names.collect();
+    (1..).map(|n| format!("{prefix} {n}")).find(|name| !taken.contains(name.as_str())).unwrap_or_default()
+}
+
+// Takes the body's ring particles with it
+fn delete_selected(
+    mut commands: Commands,
+    selection: Res<Selection>,
+    bodies: Query<&Body>,
+    particles: Query<(Entity, &RingParticle)>,
+) {
+    let Some((e, body)) = selection.0.and_then(|e| Some((e, bodies.get(e).ok()?))) else { return };
+    info!("Deleted {}", body.name);
+    commands.entity(e).despawn_recursive();
+    for (particle, _) in particles.iter().filter(|(_, particle)| particle.host == e) {
+        commands.entity(particle).despawn_recursive();
+    }
+}
+
+fn duplicate_selected(mut spawner: BodySpawner, mut selection: ResMut<Selection>, bodies: Query<(&Transform, &Body, Extras)>) {
+    let Some((trans, body, extras)) = selection.0.and_then(|e| bodies.get(e).ok()) else { return };
+    let name = free_name(&format!("{} copy", body.name), bodies.iter().map(|(_, body, _)| body.name.as_str()));
+    let copy = Body { name, ..body.clone() };
+    let position = trans.translation + DUPLICATE_OFFSET * body.radius * Vec3::X;
+    info!("Duplicated {}", body.name);
+    let extras = extras.cloned();
+    let e = if extras.test_particle { spawner.spawn_tracer(copy.clone(), position) } else { spawner.spawn(copy.clone(), position) };
+    if let Some(ring) = &extras.ring {
+        spawner.spawn_ring(e, &copy, position, ring);
+    }
+    extras.insert(spawner.commands.entity(e).insert(Transform { translation: position, ..*trans }));
+    selection.0 = Some(e);
+}
+
+#[derive(Component)]
+pub struct SpawnPlugin;
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Slingshot>()
+            .add_systems(Update, (
+                toggle_slingshot.run_if(action_just_pressed(Action::ToggleSpawn)),
+                cycle_preset.run_if(action_just_pressed(Action::CyclePreset)),
+                slingshot,
+                delete_selected.run_if(action_just_pressed(Action::DeleteBody)),
+                duplicate_selected.run_if(action_just_pressed(Action::DuplicateBody)),
+            ).in_set(Respawn));
+    }
+}
+
+#[cfg(test)]
+mod spawn_tests {
+    use super::*;
+
+    #[test]
+    fn names_take_lowest_free_number() {
+        assert_eq!(free_name("Planet", ["Sun", "Planet 2"].into_iter()), "Planet 1");
+        assert_eq!(free_name("Moon copy", ["Moon", "Moon copy 1", "Moon copy 3"].into_iter()), "Moon copy 2");
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::{prelude::*, pbr::NotShadowCaster};
+use crate::body::{Body, BodyAdded, Star};
+
+// Point light intensity per unit luminosity, in lumens
+const LUMENS_PER_LUMINOSITY: f32 = 100.;
+const LIGHT_RANGE: f32 = 100.;
+const GLOW: Color = Color::rgb(1., 0.85, 0.55);
+
+// Lamp lighting the scene while no star does
+#[derive(Component)]
+pub struct FixedLight;
+
+// Makes new stars glow and shine, casting shadows on everything but themselves
+fn light_stars(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    stars: Query<(Entity, &Star, &Body, &Handle<StandardMaterial>), Added<Star>>,
+) {
+    for (e, star, body, material) in &stars {
+        if let Some(material) = materials.get_mut(material) {
+            material.emissive = GLOW;
+        }
+        commands.entity(e).insert(NotShadowCaster).with_children(|star_body| {
+            star_body.spawn(PointLightBundle {
+                point_light: PointLight {
+                    intensity: LUMENS_PER_LUMINOSITY * star.luminosity,
+                    range: LIGHT_RANGE,
+                    radius: body.radius,
+                    shadows_enabled: true,
+                    ..default()
+                },
+                ..default()
+            });
+        });
+    }
+}
+
+fn toggle_fixed_light(stars: Query<(), With<Star>>, mut lights: Query<&mut Visibility, With<FixedLight>>) {
+    let vis = if stars.is_empty() { Visibility::Inherited } else { Visibility::Hidden };
+    for mut light in &mut lights {
+        if *light != vis {
+            *light = vis;
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct StarPlugin;
+impl Plugin for StarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (light_stars.in_set(BodyAdded), toggle_fixed_light));
+    }
+}
@@ -0,0 +1,214 @@
+use bevy::{prelude::*, ecs::system::SystemParam, ui::RelativeCursorPosition};
+use crate::{
+    body::{BodySpawner, History, HistoryBodies, Respawn, SimClock, restore_history},
+    input::{Action, action_just_pressed},
+    playback::playing,
+};
+
+const TRACK_COLOR: Color = Color::rgba(0.1, 0.1, 0.12, 0.8);
+const FILL_COLOR: Color = Color::rgb(0.2, 0.35, 0.6);
+const FONT_SIZE: f32 = 16.;
+
+#[derive(Component)]
+struct Timeline;
+#[derive(Component)]
+struct TimelineTrack;
+#[derive(Component)]
+struct TimelineFill;
+#[derive(Component)]
+struct TimelineText;
+
+fn setup_timeline(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(25.),
+                right: Val::Percent(25.),
+                bottom: Val::Px(8.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.),
+                ..default()
+            },
+            ..default()
+        },
+        Timeline,
+    )).with_children(|timeline| {
+        timeline.spawn((
+            TextBundle::from_section("", TextStyle { font_size: FONT_SIZE, color: Color::WHITE, ..default() }),
+            TimelineText,
+        ));
+        timeline.spawn((
+            ButtonBundle {
+                style: Style { width: Val::Percent(100.), height: Val::Px(16.), ..default() },
+                background_color: TRACK_COLOR.into(),
+                ..default()
+            },
+            RelativeCursorPosition::default(),
+            TimelineTrack,
+        )).with_children(|track| {
+            track.spawn((
+                NodeBundle {
+                    style: Style { width: Val::Percent(0.), height: Val::Percent(100.), ..default() },
+                    background_color: FILL_COLOR.into(),
+                    ..default()
+                },
+                TimelineFill,
+            ));
+        });
+    });
+}
+
+fn toggle_timeline(mut timeline: Query<&mut Visibility, With<Timeline>>) {
+    let mut vis = timeline.single_mut();
+    *vis = match *vis {
+        Visibility::Hidden => Visibility::Inherited,
+        _ => Visibility::Hidden,
+    };
+}
+
+// Snapshot shown on the timeline: the restored one while scrubbing, otherwise the latest
+fn shown_index(history: &History) -> Option<usize> {
+    history.cursor.or(history.snapshots.len().checked_sub(1))
+}
+
+#[derive(SystemParam)]
+struct Rewind<'w, 's> {
+    history: ResMut<'w, History>,
+    clock: ResMut<'w, SimClock>,
+    time: ResMut<'w, Time<Virtual>>,
+    spawner: BodySpawner<'w, 's>,
+    bodies: HistoryBodies<'w, 's>,
+}
+impl Rewind<'_, '_> {
+    // Pauses the simulation and restores snapshot `index`
+    fn scrub_to(&mut self, index: usize) {
+        if Some(index) == self.history.cursor || index >= self.history.snapshots.len() {
+            return;
+        }
+        self.time.pause();
+        restore_history(index, &mut self.history, &mut self.clock, &mut self.spawner, &mut self.bodies);
+    }
+}
+
+fn step_back(mut rewind: Rewind) {
+    if let Some(index) = shown_index(&rewind.history).and_then(|i| i.checked_sub(1)) {
+        rewind.scrub_to(index);
+    }
+}
+
+fn step_forward(mut rewind: Rewind) {
+    if let Some(index) = shown_index(&rewind.history) {
+        rewind.scrub_to(index + 1);
+    }
+}
+
+fn drag_timeline(track: Query<(&Interaction, &RelativeCursorPosition), With<TimelineTrack>>, mut rewind: Rewind) {
+    let Ok((Interaction::Pressed, pos)) = track.get_single() else { return };
+    let (Some(pos), Some(last)) = (pos.normalized, rewind.history.snapshots.len().checked_sub(1)) else { return };
+    rewind.scrub_to((pos.x.clamp(0., 1.) * last as f32).round() as usize);
+}
+
+fn refresh_timeline(
+    history: Res<History>,
+    clock: Res<SimClock>,
+    mut fill: Query<&mut Style, With<TimelineFill>>,
+    mut text: Query<&mut Text, With<TimelineText>>,
+) {
+    let (Some(first), Some(last)) = (history.snapshots.front(), history.snapshots.back()) else { return };
+    let span = last.clock.time - first.clock.time;
+    let fraction = if span > 0. { (clock.time - first.clock.time) / span } else { 1. };
+    fill.single_mut().width = Val::Percent(100. * fraction.clamp(0., 1.) as f32);
+    text.single_mut().sections[0].value = format!(
+        "t = {:.2} of {:.2}..{:.2}{}",
+        clock.time,
+        first.clock.time,
+        last.clock.time,
+        if history.cursor.is_some() { "  (resuming branches)" } else { "" },
+    );
+}
+
+#[derive(Component)]
+pub struct TimelinePlugin;
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_timeline)
+            .add_systems(Update, (
+                toggle_timeline.run_if(action_just_pressed(Action::ToggleTimeline)),
+                // Playback drives the bodies on its own
+                (
+                    step_back.run_if(action_just_pressed(Action::StepBack)),
+                    step_forward.run_if(action_just_pressed(Action::StepForward)),
+                    drag_timeline,
+                ).chain().run_if(not(playing)),
+                refresh_timeline,
+            ).chain().in_set(Respawn));
+    }
+}
+
+#[cfg(test)]
+mod timeline_tests {
+    use bevy::{gizmos::GizmoPlugin, input::InputPlugin, render::render_resource::Shader};
+    use crate::{
+        body::{Body, BodyExtras, BodyPlugin, Snapshot, Star},
+        frame::FramePlugin,
+        influence::{Influence, InfluencePlugin},
+        input::{InputMap, TextEntry},
+        ring::{Ring, RingParticle},
+        playback::Playback,
+        star::StarPlugin,
+    };
+    use super::*;
+
+    // Dragging restores a snapshot every frame, despawning what the last frame's restore spawned
+    // while the systems for added bodies are still settling them in
+    #[test]
+    fn dragging_respawns_every_frame() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), InputPlugin))
+            .init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<Image>()
+            .init_asset::<Shader>()
+            .init_resource::<InputMap>()
+            .init_resource::<TextEntry>()
+            .init_resource::<Playback>()
+            .add_plugins((GizmoPlugin, BodyPlugin, FramePlugin, InfluencePlugin, StarPlugin, TimelinePlugin));
+        app.world.resource_mut::<Time<Virtual>>().pause();
+        app.update();
+        let sun = Body { name: "Sun".to_owned(), mass: 10., radius: 0.5, vel: Vec3::ZERO, angular_vel: Vec3::ZERO, inertia: None };
+        let extras = BodyExtras {
+            star: Some(Star { luminosity: 1. }),
+            ring: Some(Ring { inner: 1., outer: 2., count: 20, normal: Vec3::Z, seed: 1, particle_radius: 0.01 }),
+            ..default()
+        };
+        let mut history = app.world.resource_mut::<History>();
+        history.snapshots.push_back(Snapshot {
+            clock: SimClock::default(),
+            bodies: vec![(Entity::PLACEHOLDER, Transform::IDENTITY, sun, extras)],
+        });
+        history.snapshots.push_back(Snapshot { clock: SimClock::default(), bodies: Vec::new() });
+
+        let track = app.world.query_filtered::<Entity, With<TimelineTrack>>().single(&app.world);
+        app.world.entity_mut(track).insert(Interaction::Pressed);
+        for k in 0..6 {
+            let x = if k % 2 == 0 { 0. } else { 1. };
+            app.world.get_mut::<RelativeCursorPosition>(track).unwrap().normalized = Some(Vec2::new(x, 0.5));
+            app.update();
+        }
+        app.update();
+        let sun = app.world.query::<(Entity, &Body)>().iter(&app.world)
+            .find(|(_, body)| body.name == "Sun")
+            .map(|(e, _)| e);
+        assert_eq!(sun, None);
+        // Back onto the star, which settles in like any new body
+        app.world.get_mut::<RelativeCursorPosition>(track).unwrap().normalized = Some(Vec2::new(0., 0.5));
+        app.update();
+        app.update();
+        let (sun, _) = app.world.query::<(Entity, &Body)>().iter(&app.world).find(|(_, body)| body.name == "Sun").unwrap();
+        assert!(app.world.get::<Parent>(sun).is_some());
+        assert!(app.world.get::<Influence>(sun).is_some());
+        assert!(app.world.get::<Children>(sun).is_some_and(|lights| lights.iter().any(|&e| app.world.get::<PointLight>(e).is_some())));
+        assert_eq!(app.world.query::<&RingParticle>().iter(&app.world).count(), 20);
+    }
+}
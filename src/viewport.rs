@@ -0,0 +1,88 @@
+use bevy::{prelude::*, render::camera::{ScalingMode, Viewport}, transform::TransformSystem, window::PrimaryWindow};
+use crate::{camera::{CenterCam, follow_focus}, input::{Action, action_just_pressed}};
+
+// Orthographic views shown beside the main camera: name, direction they look from and up
+const VIEWS: [(&str, Vec3, Vec3); 3] = [
+    ("XY", Vec3::Z, Vec3::Y),
+    ("XZ", Vec3::NEG_Y, Vec3::Z),
+    ("YZ", Vec3::X, Vec3::Z),
+];
+// Orthographic cameras sit this far back from the focus, well inside their far plane
+const VIEW_DISTANCE: f32 = 500.;
+
+#[derive(Resource, Default)]
+pub struct SplitView(pub bool);
+
+#[derive(Component)]
+pub struct OrthoView {
+    from: Vec3,
+    up: Vec3,
+}
+
+fn setup_views(mut commands: Commands) {
+    for (i, (name, from, up)) in VIEWS.into_iter().enumerate() {
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera { order: i as isize + 1, is_active: false, ..default() },
+                projection: Projection::Orthographic(OrthographicProjection {
+                    far: 2. * VIEW_DISTANCE,
+                    ..default()
+                }),
+                ..default()
+            },
+            UiCameraConfig { show_ui: false },
+            OrthoView { from, up },
+            Name::new(format!("{name} view")),
+        ));
+    }
+}
+
+fn toggle_split(mut split: ResMut<SplitView>) {
+    split.0 = !split.0;
+}
+
+// Main view takes the left two thirds of the window, the orthographic views stack on the right
+fn layout_views(
+    split: Res<SplitView>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut main: Query<(&mut Camera, &Transform, &CenterCam), Without<OrthoView>>,
+    mut views: Query<(&mut Camera, &mut Transform, &mut Projection, &OrthoView)>,
+) {
+    let Ok(window) = window.get_single() else { return };
+    let Ok((mut main_cam, main_trans, center)) = main.get_single_mut() else { return };
+    let (w, h) = (window.physical_width(), window.physical_height());
+    if !split.0 || w < 3 || h < 3 {
+        main_cam.viewport = None;
+        for (mut cam, ..) in &mut views {
+            cam.is_active = false;
+        }
+        return;
+    }
+    let (cw, vh) = (w / 3, h / 3);
+    main_cam.viewport = Some(Viewport { physical_position: UVec2::ZERO, physical_size: UVec2::new(w - cw, h), ..default() });
+    let focus = main_trans.translation - center.offset;
+    let extent = 2. * center.offset.length();
+    for (i, (mut cam, mut trans, mut proj, view)) in views.iter_mut().enumerate() {
+        cam.is_active = true;
+        cam.viewport = Some(Viewport {
+            physical_position: UVec2::new(w - cw, i as u32 * vh),
+            physical_size: UVec2::new(cw, vh),
+            ..default()
+        });
+        *trans = Transform::from_translation(focus + VIEW_DISTANCE * view.from).looking_at(focus, view.up);
+        if let Projection::Orthographic(p) = proj.as_mut() {
+            p.scaling_mode = ScalingMode::FixedVertical(extent);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ViewportPlugin;
+impl Plugin for ViewportPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplitView>()
+            .add_systems(Startup, setup_views)
+            .add_systems(Update, toggle_split.run_if(action_just_pressed(Action::SplitView)))
+            .add_systems(PostUpdate, layout_views.after(follow_focus).before(TransformSystem::TransformPropagate));
+    }
+}